@@ -0,0 +1,482 @@
+//! The player's car: driving, bouncing off walls and its controls.
+
+use std::collections::BTreeMap;
+
+use bevy::{input::InputSystem, prelude::*};
+
+use crate::{
+    controls::{Action, Controls},
+    delivery::{lv1_ammo, Merch},
+    events::WallHit,
+    ghost,
+    replay::ReplayState,
+    run_if_no_pending_transition, run_if_not_resuming,
+    sprites::{set_transformation, Camera},
+    tick_scale,
+    track::Obstacle,
+    vehicle::{Garage, SelectedVehicle, Vehicle, VehicleLoader, VehicleStats, Vehicles},
+    AppState, TickInput, TickSet, HEIGHT_OF_WALL,
+};
+
+/// Driving the car and the vehicles it can be, and with `render` its sprite, the ghost car
+/// and the controls
+pub struct CarPlugin {
+    pub render: bool,
+}
+
+impl Plugin for CarPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Vehicle>()
+            .init_asset_loader::<VehicleLoader>()
+            .add_systems(Startup, setup_garage)
+            .add_systems(
+                FixedUpdate,
+                (sprite_movement, collision_update_system)
+                    .chain()
+                    .in_set(TickSet::Drive),
+            );
+        if !self.render {
+            return;
+        }
+        app.init_resource::<ghost::GhostSource>()
+            .add_systems(PreUpdate, gather_input.after(InputSystem))
+            .add_systems(
+                Update,
+                (load_car_sprites, car_draw, ghost::ghost_draw).chain(),
+            )
+            .add_systems(
+                FixedUpdate,
+                ghost::ghost_splits
+                    .after(TickSet::Outcome)
+                    .run_if(in_state(AppState::Game))
+                    .run_if(run_if_no_pending_transition),
+            )
+            .add_systems(
+                OnEnter(AppState::Game),
+                ghost::spawn_ghost
+                    .after(crate::replay::begin_run)
+                    .run_if(run_if_not_resuming),
+            );
+    }
+}
+
+/// Health a car starts every run with
+pub const MAX_HEALTH: f32 = 100.0;
+
+/// Wall hits slower than this, sideways, only scrape the paint
+const HARMLESS_IMPACT_SPEED: f32 = 5.0;
+
+/// Damage per unit of impact speed above `HARMLESS_IMPACT_SPEED`
+const DAMAGE_PER_IMPACT_SPEED: f32 = 1.0;
+
+/// How well a car with no health left would still drive, as a share of its top speed and steering
+const WRECKED_PERFORMANCE: f32 = 0.5;
+
+/// Share of its speed the car keeps when it is knocked back off a cone
+const KNOCKBACK_SPEED: f32 = 0.3;
+
+/// Slowest the car is knocked back at, so it always rolls clear of the cone while stunned
+const KNOCKBACK_MIN_SPEED: f32 = 5.0;
+
+/// Reference ticks the car can't drive for after it is knocked back
+pub const KNOCKBACK_STUN_TICKS: f32 = 30.0;
+
+#[derive(Component)]
+pub struct Car {
+    pub pos: Vec2,
+    pub vel: Vec2, // Velocity is calculated
+    pub direction: Vec2,
+    // State at the previous tick, to interpolate between ticks when drawing
+    prev_pos: Vec2,
+    prev_vel: Vec2,
+    prev_direction: Vec2,
+    /// Input of the latest tick, to pick the sprite
+    input: TickInput,
+    /// Index into `vehicle::GARAGE`, the stats below come from it
+    pub vehicle: usize,
+    pub base_acc: f32,
+    pub top_speed: f32,
+    pub steer_strength: f32,
+    pub drift_strength: f32,
+    /// How quickly the brake slows the car down
+    pub brake_strength: f32,
+    pub reverse_acc: f32,
+    pub reverse_top_speed: f32,
+    /// Replaces `drift_strength` while the handbrake is held, so the car slides
+    pub handbrake_drift_strength: f32,
+    /// Driving backwards after braking to a standstill
+    pub reversing: bool,
+    pub projectile_speed: f32,
+    pub ammo: BTreeMap<Merch, usize>,
+    /// The merch that is thrown next, one of the kinds in `ammo`
+    pub selected: Merch,
+    /// The ammo the car started the level with, full restocks go back up to it
+    pub starting_ammo: BTreeMap<Merch, usize>,
+    pub ticks_elapsed: usize,
+    pub hard_mode: bool,
+    pub money: usize,
+    pub delivered: usize,
+    /// Customers who ran out of patience before they got their merch
+    pub missed: usize,
+    pub crashes: usize,
+    /// From `MAX_HEALTH` down to 0, which ends the run. Damage slows the car and its steering.
+    pub health: f32,
+    /// Reference ticks until the car can drive again after being knocked back, its input
+    /// is ignored until then
+    pub stun: f32,
+}
+
+impl Car {
+    /// Time since the start of the run in milliseconds
+    pub fn time_ms(&self, fixed_time: &Time<Fixed>) -> u64 {
+        (self.ticks_elapsed as f64 * fixed_time.timestep().as_secs_f64() * 1000.0) as u64
+    }
+
+    /// A fresh car on the start line. It can't drive until `set_vehicle` tunes it like a loaded
+    /// vehicle.
+    pub fn new() -> Self {
+        Car {
+            pos: Vec2::new(100., 0.),
+            vel: Vec2::new(0., 0.),
+            direction: Vec2::new(0., 1.),
+            prev_pos: Vec2::new(100., 0.),
+            prev_vel: Vec2::new(0., 0.),
+            prev_direction: Vec2::new(0., 1.),
+            input: TickInput::default(),
+            vehicle: 0,
+            base_acc: 0.,
+            top_speed: 0.,
+            steer_strength: 0.,
+            drift_strength: 0.,
+            brake_strength: 0.,
+            reverse_acc: 0.,
+            reverse_top_speed: 0.,
+            handbrake_drift_strength: 0.,
+            reversing: false,
+            projectile_speed: 0.,
+            ammo: lv1_ammo(),
+            selected: Merch::Banana,
+            starting_ammo: lv1_ammo(),
+            ticks_elapsed: 0,
+            hard_mode: false,
+            money: 0,
+            delivered: 0,
+            missed: 0,
+            crashes: 0,
+            health: MAX_HEALTH,
+            stun: 0.,
+        }
+    }
+
+    /// Tunes the car like vehicle `index` of the garage
+    pub fn set_vehicle(&mut self, index: usize, stats: &VehicleStats) {
+        self.vehicle = index;
+        self.base_acc = stats.base_acc;
+        self.top_speed = stats.top_speed;
+        self.steer_strength = stats.steer_strength;
+        self.drift_strength = stats.drift_strength;
+        self.brake_strength = stats.brake_strength;
+        self.reverse_acc = stats.reverse_acc;
+        self.reverse_top_speed = stats.reverse_top_speed;
+        self.handbrake_drift_strength = stats.handbrake_drift_strength;
+        self.projectile_speed = stats.projectile_speed;
+    }
+
+    /// Loads the car with `ammo` and selects the first kind of merch in it
+    pub fn load_ammo(&mut self, ammo: BTreeMap<Merch, usize>) {
+        self.selected = ammo.keys().next().copied().unwrap_or_default();
+        self.starting_ammo = ammo.clone();
+        self.ammo = ammo;
+    }
+
+    /// Selects the next kind of merch the car carries, wrapping around to the first
+    pub fn cycle_merch(&mut self) {
+        let mut kinds = self.ammo.keys().copied();
+        let first = kinds.clone().next();
+        if let Some(next) = kinds.find(|kind| *kind > self.selected).or(first) {
+            self.selected = next;
+        }
+    }
+
+    /// Damages the car for hitting a wall at `speed` sideways. Hardcore cars take no damage,
+    /// the first hit ends their run.
+    pub fn take_wall_damage(&mut self, speed: f32) {
+        if self.hard_mode {
+            return;
+        }
+        let damage = (speed - HARMLESS_IMPACT_SPEED).max(0.0) * DAMAGE_PER_IMPACT_SPEED;
+        self.health = (self.health - damage).max(0.0);
+    }
+
+    /// How well the car still drives, from 1 undamaged down to `WRECKED_PERFORMANCE`
+    fn performance(&self) -> f32 {
+        WRECKED_PERFORMANCE + (1.0 - WRECKED_PERFORMANCE) * self.health / MAX_HEALTH
+    }
+
+    /// Sends the car rolling away from a cone at `from`, slowing it down and stunning it for a
+    /// moment. The walls still bounce it like any other move.
+    pub fn knock_back(&mut self, from: Vec2) {
+        let away = (self.pos - from).try_normalize().unwrap_or(-self.direction);
+        let speed = (self.vel.length() * KNOCKBACK_SPEED).max(KNOCKBACK_MIN_SPEED);
+        self.vel = away * speed;
+        self.stun = KNOCKBACK_STUN_TICKS;
+    }
+
+    /// Advances the car's physics by one tick
+    pub fn drive(&mut self, input: &TickInput, step: f32) {
+        // a stunned car rolls on without steering or pedals
+        let stunned = TickInput::default();
+        let input = if self.stun > 0.0 {
+            self.stun = (self.stun - step).max(0.0);
+            &stunned
+        } else {
+            input
+        };
+        self.ticks_elapsed += 1;
+        self.prev_pos = self.pos;
+        self.prev_vel = self.vel;
+        self.prev_direction = self.direction;
+        self.input = *input;
+        let forward_speed = self.vel.dot(self.direction);
+        if forward_speed > 0.0 {
+            self.reversing = false;
+        }
+        if input.steer != 0.0 {
+            // Steering speed depends on speed of the car, and turns the other way in reverse.
+            let steer = if self.reversing {
+                -input.steer
+            } else {
+                input.steer
+            };
+            self.direction = self.direction.rotate(Vec2::from_angle(
+                self.steer_strength * self.performance() * steer * self.vel.length() * step,
+            ));
+        }
+
+        let mut car_velocity_update = Vec2::new(0.0, 0.0);
+        if input.throttle > 0.0 {
+            let min2 = (self.vel.length() / 10.0).clamp(0.1, 1.0);
+
+            car_velocity_update += self.direction * (self.base_acc * input.throttle) * min2;
+        }
+        if input.brake > 0.0 {
+            if forward_speed > 0.5 {
+                // don't brake past a standstill
+                let braking = (self.brake_strength * input.brake).min(self.vel.length() / step);
+                car_velocity_update -= self.vel.normalize() * braking;
+            } else {
+                self.reversing = true;
+                car_velocity_update -= self.direction * (self.reverse_acc * input.brake);
+            }
+        }
+        if self.vel.length() > 0.000001 {
+            // the car slides less the more its wheels line up with where it's going
+            let facing = if self.reversing {
+                -self.direction
+            } else {
+                self.direction
+            };
+            let drift_strength = if input.handbrake {
+                self.handbrake_drift_strength
+            } else {
+                self.drift_strength
+            };
+            car_velocity_update -= self.vel.angle_between(facing).abs() * self.vel * drift_strength;
+        }
+
+        self.vel += car_velocity_update * step;
+
+        // Limit the length of the vector to car.top_speed, or car.reverse_top_speed backwards
+        let top_speed = if self.reversing {
+            self.reverse_top_speed
+        } else {
+            self.top_speed * self.performance()
+        };
+        if self.vel.length() > top_speed {
+            self.vel = self.vel.normalize() * top_speed;
+        }
+
+        self.pos += self.vel * step;
+    }
+
+    /// Bounces the car off the walls it is touching, returns how fast it hit them sideways
+    /// if it hit any
+    pub fn bounce_off_walls<'a>(
+        &mut self,
+        obstacles: impl IntoIterator<Item = &'a Obstacle>,
+        step: f32,
+    ) -> Option<f32> {
+        let speed = self.vel.x.abs();
+        let mut hit = false;
+        for obstacle in obstacles {
+            if (obstacle.pos.x - self.pos.x).abs() < 100.
+                && (obstacle.pos.y - self.pos.y).abs() < 2. * HEIGHT_OF_WALL
+                && obstacle.bounce_dir * self.vel.x < 0.
+            {
+                self.vel.x = -0.9 * self.vel.x + 0.15 * obstacle.bounce_dir * self.top_speed;
+                self.pos += self.vel * step;
+                self.vel.x *= 0.6;
+                self.vel.y *= 0.3;
+                hit = true;
+            }
+        }
+        hit.then_some(speed)
+    }
+
+    /// Where the camera should be, `alpha` of the way from the previous tick to the current one
+    pub(crate) fn view(&self, alpha: f32) -> CarView {
+        CarView {
+            pos: self.prev_pos.lerp(self.pos, alpha),
+            vel: self.prev_vel.lerp(self.vel, alpha),
+            direction: self
+                .prev_direction
+                .lerp(self.direction, alpha)
+                .normalize_or_zero(),
+            tilt: 1.0,
+        }
+    }
+}
+
+impl Default for Car {
+    fn default() -> Self {
+        Car::new()
+    }
+}
+
+/// The car as seen by the renderer
+pub(crate) struct CarView {
+    pub pos: Vec2,
+    pub vel: Vec2,
+    pub direction: Vec2,
+    /// Scales how far the camera tilts forward, 1 is the original tilt
+    pub tilt: f32,
+}
+
+fn setup_garage(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    replay_state: Res<ReplayState>,
+) {
+    commands.insert_resource(Garage::load(&asset_server));
+    commands.insert_resource(SelectedVehicle {
+        index: replay_state.starting_vehicle(),
+    });
+}
+
+/// Advances the car by one simulation tick.
+fn sprite_movement(mut cars: Query<&mut Car>, input: Res<TickInput>, fixed_time: Res<Time<Fixed>>) {
+    let step = tick_scale(&fixed_time);
+    for mut car in &mut cars {
+        car.drive(&input, step);
+    }
+}
+
+fn collision_update_system(
+    obstacles: Query<&Obstacle>,
+    mut car: Query<&mut Car>,
+    fixed_time: Res<Time<Fixed>>,
+    mut wall_hits: EventWriter<WallHit>,
+) {
+    let mut car = car.get_single_mut().unwrap();
+    if let Some(speed) = car.bounce_off_walls(&obstacles, tick_scale(&fixed_time)) {
+        wall_hits.send(WallHit {
+            pos: car.pos,
+            speed,
+        });
+    }
+}
+
+/// Reads the bound keys and gamepad buttons into `TickInput` for the simulation
+fn gather_input(controls: Controls, mut input: ResMut<TickInput>) {
+    input.throttle = controls.value(Action::Accelerate);
+    input.brake = controls.value(Action::Brake);
+    input.handbrake = controls.pressed(Action::Handbrake);
+    input.steer = if controls.pressed(Action::SteerLeft) {
+        1.0
+    } else if controls.pressed(Action::SteerRight) {
+        -1.0
+    } else {
+        // the stick is positive to the right, steering is positive to the left
+        -controls.stick_x()
+    };
+    input.shoot_left |= controls.just_pressed(Action::ShootLeft);
+    input.shoot_right |= controls.just_pressed(Action::ShootRight);
+    input.cycle_merch |= controls.just_pressed(Action::CycleMerch);
+}
+
+/// The sprites of the vehicle the car is, loaded once it is picked
+#[derive(Component)]
+struct CarSprites {
+    /// Index into `vehicle::GARAGE`
+    vehicle: usize,
+    center: Handle<Image>,
+    left: Handle<Image>,
+    right: Handle<Image>,
+    scale: f32,
+}
+
+/// Gives a car the sprites of its vehicle when it is spawned and whenever another is picked.
+/// `car_draw` shows it once they have loaded.
+fn load_car_sprites(
+    mut commands: Commands,
+    cars: Query<(Entity, &Car, Option<&CarSprites>)>,
+    asset_server: Res<AssetServer>,
+    vehicles: Vehicles,
+) {
+    for (entity, car, sprites) in &cars {
+        if sprites.is_some_and(|sprites| sprites.vehicle == car.vehicle) {
+            continue;
+        }
+        let Some(vehicle) = vehicles.get(car.vehicle) else {
+            continue;
+        };
+        let center = asset_server.load(vehicle.sprites.center.clone());
+        commands.entity(entity).insert((
+            SpriteBundle {
+                texture: center.clone(),
+                transform: Transform::from_scale(Vec3::ZERO),
+                ..default()
+            },
+            CarSprites {
+                vehicle: car.vehicle,
+                center,
+                left: asset_server.load(vehicle.sprites.left.clone()),
+                right: asset_server.load(vehicle.sprites.right.clone()),
+                scale: vehicle.sprites.scale,
+            },
+        ));
+    }
+}
+
+fn car_draw(
+    mut car_query: Query<(&Car, &CarSprites, &mut Transform, &mut Handle<Image>)>,
+    assets: Res<Assets<Image>>,
+    camera: Camera,
+) {
+    for (car, sprites, mut transform, mut texture) in &mut car_query {
+        // sized from the vehicle's own sprite
+        if let Some(sprite) = assets.get(&sprites.center) {
+            let steering = if car.input.steer > 0.0 {
+                &sprites.left
+            } else if car.input.steer < 0.0 {
+                &sprites.right
+            } else {
+                &sprites.center
+            };
+            if *texture != *steering {
+                *texture = steering.clone();
+            }
+            // Update sprite
+            let view = camera.view();
+            set_transformation(
+                &mut transform,
+                &view.pos,
+                sprites.scale,
+                &view,
+                sprite.size_f32(),
+            );
+            transform.rotation =
+                Quat::from_rotation_z(view.direction.to_angle() - std::f32::consts::FRAC_PI_2);
+        }
+    }
+}
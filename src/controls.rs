@@ -0,0 +1,565 @@
+//! Controls as actions: which key and gamepad button does what, and the screen to rebind them.
+//!
+//! Gameplay and menus only ask `Controls` about actions, never about keys, so
+//! every binding can be changed by the player. The analog stick always steers.
+
+use std::collections::BTreeMap;
+
+use bevy::{ecs::system::SystemParam, input::InputSystem, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    run_if_in_start_level,
+    save::Storage,
+    settings::{self, Settings},
+    AppState,
+};
+
+/// The controls screen, opened from the settings screen. Needs `SettingsPlugin`.
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ControlsScreen>()
+            .add_systems(
+                PreUpdate,
+                controls_screen_input
+                    .after(InputSystem)
+                    .run_if(run_if_in_start_level.or_else(in_state(AppState::Paused))),
+            )
+            .add_systems(
+                Update,
+                show_controls_screen.run_if(
+                    resource_changed::<ControlsScreen>.or_else(resource_changed::<Settings>),
+                ),
+            );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum Action {
+    Accelerate,
+    /// Brake, and reverse once stopped
+    Brake,
+    Handbrake,
+    SteerLeft,
+    SteerRight,
+    ShootLeft,
+    ShootRight,
+    /// Select the next kind of merch to throw
+    CycleMerch,
+    /// Start a level, or restart it from the end screen
+    Confirm,
+    /// Start a level in hardcore mode
+    Hardcore,
+    /// Open or close the vehicle select screen
+    PickVehicle,
+    Pause,
+    /// Go on to the next level from the end screen
+    NextLevel,
+    /// Watch the run that just ended from the end screen
+    Replay,
+    /// Pick the item above in a menu
+    MenuUp,
+    /// Pick the item below in a menu
+    MenuDown,
+    /// Pick the previous level, like steering left
+    MenuLeft,
+    /// Pick the next level, like steering right
+    MenuRight,
+}
+
+impl Action {
+    /// Every action, in the order the controls screen lists them
+    pub const ALL: [Action; 18] = [
+        Action::Accelerate,
+        Action::Brake,
+        Action::Handbrake,
+        Action::SteerLeft,
+        Action::SteerRight,
+        Action::ShootLeft,
+        Action::ShootRight,
+        Action::CycleMerch,
+        Action::Confirm,
+        Action::Hardcore,
+        Action::PickVehicle,
+        Action::Pause,
+        Action::NextLevel,
+        Action::Replay,
+        Action::MenuUp,
+        Action::MenuDown,
+        Action::MenuLeft,
+        Action::MenuRight,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Accelerate => "Accelerate",
+            Action::Brake => "Brake / reverse",
+            Action::Handbrake => "Handbrake",
+            Action::SteerLeft => "Steer left",
+            Action::SteerRight => "Steer right",
+            Action::ShootLeft => "Shoot left",
+            Action::ShootRight => "Shoot right",
+            Action::CycleMerch => "Switch merch",
+            Action::Confirm => "Confirm",
+            Action::Hardcore => "Hardcore",
+            Action::PickVehicle => "Pick vehicle",
+            Action::Pause => "Pause",
+            Action::NextLevel => "Next level",
+            Action::Replay => "Watch replay",
+            Action::MenuUp => "Menu up",
+            Action::MenuDown => "Menu down",
+            Action::MenuLeft => "Menu left",
+            Action::MenuRight => "Menu right",
+        }
+    }
+
+    /// Whether the action is only used on the menu screens, never while driving
+    fn is_menu(self) -> bool {
+        matches!(
+            self,
+            Action::Confirm
+                | Action::Hardcore
+                | Action::PickVehicle
+                | Action::NextLevel
+                | Action::Replay
+                | Action::MenuUp
+                | Action::MenuDown
+                | Action::MenuLeft
+                | Action::MenuRight
+        )
+    }
+
+    /// A gamepad has too few buttons for every action, so menu actions share them with driving
+    /// ones. No two menu actions or two driving actions share one.
+    fn default_binding(self) -> Binding {
+        let (key, button) = match self {
+            Action::Accelerate => (KeyCode::KeyW, Some(GamepadButtonType::RightTrigger2)),
+            Action::Brake => (KeyCode::KeyS, Some(GamepadButtonType::LeftTrigger2)),
+            Action::Handbrake => (KeyCode::ShiftLeft, Some(GamepadButtonType::East)),
+            Action::SteerLeft => (KeyCode::KeyA, Some(GamepadButtonType::DPadLeft)),
+            Action::SteerRight => (KeyCode::KeyD, Some(GamepadButtonType::DPadRight)),
+            Action::ShootLeft => (KeyCode::KeyJ, Some(GamepadButtonType::LeftTrigger)),
+            Action::ShootRight => (KeyCode::KeyK, Some(GamepadButtonType::RightTrigger)),
+            Action::CycleMerch => (KeyCode::KeyL, Some(GamepadButtonType::West)),
+            Action::Confirm => (KeyCode::Space, Some(GamepadButtonType::South)),
+            Action::Hardcore => (KeyCode::KeyH, Some(GamepadButtonType::North)),
+            Action::PickVehicle => (KeyCode::KeyV, Some(GamepadButtonType::West)),
+            Action::Pause => (KeyCode::Escape, Some(GamepadButtonType::Start)),
+            Action::NextLevel => (KeyCode::KeyN, Some(GamepadButtonType::East)),
+            Action::Replay => (KeyCode::KeyR, Some(GamepadButtonType::RightThumb)),
+            Action::MenuUp => (KeyCode::ArrowUp, Some(GamepadButtonType::DPadUp)),
+            Action::MenuDown => (KeyCode::ArrowDown, Some(GamepadButtonType::DPadDown)),
+            Action::MenuLeft => (KeyCode::ArrowLeft, Some(GamepadButtonType::DPadLeft)),
+            Action::MenuRight => (KeyCode::ArrowRight, Some(GamepadButtonType::DPadRight)),
+        };
+        Binding { key, button }
+    }
+}
+
+/// The key and gamepad button bound to one action
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Binding {
+    pub key: KeyCode,
+    pub button: Option<GamepadButtonType>,
+}
+
+/// What every action is bound to. Actions missing from a saved map keep their default.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct InputMap {
+    bindings: BTreeMap<Action, Binding>,
+}
+
+impl InputMap {
+    pub fn binding(&self, action: Action) -> Binding {
+        self.bindings
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_binding())
+    }
+
+    /// Binds `key` to `action`. The action that had it before gets `action`'s old key,
+    /// so nothing is ever left unbound.
+    pub fn bind_key(&mut self, action: Action, key: KeyCode) {
+        let old = self.binding(action).key;
+        for other in Action::ALL {
+            if other != action && self.binding(other).key == key {
+                self.bindings.insert(
+                    other,
+                    Binding {
+                        key: old,
+                        ..self.binding(other)
+                    },
+                );
+            }
+        }
+        self.bindings.insert(
+            action,
+            Binding {
+                key,
+                ..self.binding(action)
+            },
+        );
+    }
+
+    /// Binds `button` to `action`, swapping like `bind_key` with the action of the same kind
+    /// that had it. Menu and driving actions share buttons, so one of the other kind keeps it.
+    /// If `action` had no button, the other action keeps it too.
+    pub fn bind_button(&mut self, action: Action, button: GamepadButtonType) {
+        let old = self.binding(action).button;
+        for other in Action::ALL {
+            if other != action
+                && other.is_menu() == action.is_menu()
+                && old.is_some()
+                && self.binding(other).button == Some(button)
+            {
+                self.bindings.insert(
+                    other,
+                    Binding {
+                        button: old,
+                        ..self.binding(other)
+                    },
+                );
+            }
+        }
+        self.bindings.insert(
+            action,
+            Binding {
+                button: Some(button),
+                ..self.binding(action)
+            },
+        );
+    }
+
+    /// A short description of what `action` is bound to, like "W / RightTrigger2"
+    pub fn describe(&self, action: Action) -> String {
+        let binding = self.binding(action);
+        match binding.button {
+            Some(button) => format!("{} / {button:?}", key_name(binding.key)),
+            None => key_name(binding.key),
+        }
+    }
+}
+
+/// A key's name without the "Key" in front of letters
+pub fn key_name(key: KeyCode) -> String {
+    let name = format!("{key:?}");
+    name.strip_prefix("Key").unwrap_or(&name).to_string()
+}
+
+/// The state of every action this frame, from the keyboard and all gamepads
+#[derive(SystemParam)]
+pub struct Controls<'w> {
+    settings: Res<'w, Settings>,
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    gamepads: Res<'w, Gamepads>,
+    buttons: Res<'w, ButtonInput<GamepadButton>>,
+    button_axes: Res<'w, Axis<GamepadButton>>,
+    sticks: Res<'w, Axis<GamepadAxis>>,
+}
+
+impl Controls<'_> {
+    pub fn pressed(&self, action: Action) -> bool {
+        let binding = self.settings.controls.binding(action);
+        self.keys.pressed(binding.key)
+            || binding.button.is_some_and(|button| {
+                self.gamepads
+                    .iter()
+                    .any(|gamepad| self.buttons.pressed(GamepadButton::new(gamepad, button)))
+            })
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        let binding = self.settings.controls.binding(action);
+        self.keys.just_pressed(binding.key)
+            || binding.button.is_some_and(|button| {
+                self.gamepads.iter().any(|gamepad| {
+                    self.buttons
+                        .just_pressed(GamepadButton::new(gamepad, button))
+                })
+            })
+    }
+
+    /// How far `action` is pressed from 0 to 1, for buttons that are analog like triggers
+    pub fn value(&self, action: Action) -> f32 {
+        let binding = self.settings.controls.binding(action);
+        if self.keys.pressed(binding.key) {
+            return 1.0;
+        }
+        binding.button.map_or(0.0, |button| {
+            self.gamepads
+                .iter()
+                .map(|gamepad| {
+                    let button = GamepadButton::new(gamepad, button);
+                    // digital buttons may not report a value
+                    let pressed = if self.buttons.pressed(button) {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                    self.button_axes.get(button).unwrap_or(pressed)
+                })
+                .fold(0.0, f32::max)
+        })
+    }
+
+    /// The left stick of the first gamepad that is pushed sideways, positive to the right
+    pub fn stick_x(&self) -> f32 {
+        self.gamepads
+            .iter()
+            .filter_map(|gamepad| {
+                self.sticks
+                    .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
+            })
+            .find(|x| *x != 0.0)
+            .unwrap_or(0.0)
+    }
+}
+
+/// Whether the controls screen is open, and which action on it is picked
+#[derive(Resource, Default)]
+pub struct ControlsScreen {
+    pub open: bool,
+    selected: usize,
+    /// The picked action gets the next key or gamepad button pressed
+    waiting: bool,
+}
+
+#[derive(Component)]
+struct PartOfControlsScreen;
+
+/// The keys that work the controls and settings screens. They can't be picked when rebinding,
+/// only the menu actions have them by default.
+const SCREEN_KEYS: [KeyCode; 7] = [
+    KeyCode::Tab,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::Enter,
+    KeyCode::Backspace,
+];
+
+/// Rebinds the picked action, and closes the controls screen again.
+///
+/// Actions can't be bound to the keys that work the screens, so a player can't lock themselves
+/// out. Gamepad buttons can all be bound, some of them are actions' defaults, and the keyboard
+/// always works the screens.
+pub(crate) fn controls_screen_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    mut screen: ResMut<ControlsScreen>,
+    mut settings: ResMut<Settings>,
+    storage: Res<Storage>,
+) {
+    let pad = |button| {
+        gamepads
+            .iter()
+            .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, button)))
+    };
+    if !screen.open {
+        return;
+    }
+    let action = Action::ALL[screen.selected];
+
+    if screen.waiting {
+        if keys.just_pressed(KeyCode::Tab) {
+            // keep the old binding
+            screen.waiting = false;
+            return;
+        }
+        if let Some(&key) = keys
+            .get_just_pressed()
+            .find(|key| !SCREEN_KEYS.contains(key))
+        {
+            settings.controls.bind_key(action, key);
+        } else if let Some(button) = buttons.get_just_pressed().next() {
+            settings.controls.bind_button(action, button.button_type);
+        } else {
+            return;
+        }
+        screen.waiting = false;
+        settings::store(&storage, &settings);
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::Tab) || pad(GamepadButtonType::Select) {
+        screen.open = false;
+        screen.selected = 0;
+        return;
+    }
+    if keys.just_pressed(KeyCode::ArrowUp) || pad(GamepadButtonType::DPadUp) {
+        screen.selected = (screen.selected + Action::ALL.len() - 1) % Action::ALL.len();
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) || pad(GamepadButtonType::DPadDown) {
+        screen.selected = (screen.selected + 1) % Action::ALL.len();
+    }
+    if keys.just_pressed(KeyCode::Enter) || pad(GamepadButtonType::South) {
+        screen.waiting = true;
+    }
+    // only from the keyboard, a gamepad button could be pressed by accident
+    if keys.just_pressed(KeyCode::Backspace) {
+        settings.controls = InputMap::default();
+        settings::store(&storage, &settings);
+    }
+}
+
+/// Rebuilds the controls screen whenever it or the bindings change.
+fn show_controls_screen(
+    mut commands: Commands,
+    screen: Res<ControlsScreen>,
+    settings: Res<Settings>,
+    old: Query<Entity, With<PartOfControlsScreen>>,
+) {
+    for entity in &old {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !screen.open {
+        return;
+    }
+
+    // smaller than the settings screen, to fit every action
+    let style = TextStyle {
+        font_size: 30.0,
+        color: Color::GOLD,
+        ..default()
+    };
+    let mut lines = vec!["Controls".to_string()];
+    for (index, action) in Action::ALL.into_iter().enumerate() {
+        let binding = if index == screen.selected && screen.waiting {
+            "press a key or button...".to_string()
+        } else {
+            settings.controls.describe(action)
+        };
+        let cursor = if index == screen.selected { "> " } else { "  " };
+        lines.push(format!("{cursor}{}: {binding}", action.label()));
+    }
+    let help = if screen.waiting {
+        "Tab: keep the old binding"
+    } else {
+        "Up/Down: pick, Enter: rebind, Tab: back, Backspace on the keyboard: defaults"
+    };
+    lines.push(help.to_string());
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    padding: UiRect::left(Val::Percent(20.0)),
+                    ..default()
+                },
+                background_color: Color::BLACK.into(),
+                // in front of the settings screen
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            PartOfControlsScreen,
+        ))
+        .with_children(|parent| {
+            for line in lines {
+                parent.spawn(TextBundle::from_section(line, style.clone()));
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binding_a_taken_key_swaps_it_with_the_other_action() {
+        let mut map = InputMap::default();
+        map.bind_key(Action::Accelerate, KeyCode::KeyJ);
+        assert_eq!(map.binding(Action::Accelerate).key, KeyCode::KeyJ);
+        assert_eq!(map.binding(Action::ShootLeft).key, KeyCode::KeyW);
+        // the buttons stay where they were
+        assert_eq!(
+            map.binding(Action::ShootLeft).button,
+            Some(GamepadButtonType::LeftTrigger)
+        );
+    }
+
+    #[test]
+    fn binding_a_taken_button_swaps_it_with_the_other_action() {
+        let mut map = InputMap::default();
+        map.bind_button(Action::Confirm, GamepadButtonType::North);
+        assert_eq!(
+            map.binding(Action::Confirm).button,
+            Some(GamepadButtonType::North)
+        );
+        assert_eq!(
+            map.binding(Action::Hardcore).button,
+            Some(GamepadButtonType::South)
+        );
+        assert_eq!(map.binding(Action::Hardcore).key, KeyCode::KeyH);
+    }
+
+    /// Whether two actions of the same kind share a button
+    fn shares_a_button(map: &InputMap) -> bool {
+        Action::ALL.into_iter().any(|action| {
+            Action::ALL.into_iter().any(|other| {
+                other != action
+                    && other.is_menu() == action.is_menu()
+                    && map.binding(other).button == map.binding(action).button
+            })
+        })
+    }
+
+    #[test]
+    fn a_button_shared_with_a_menu_action_swaps_with_the_driving_one_only() {
+        let mut map = InputMap::default();
+        assert!(!shares_a_button(&map));
+        map.bind_button(Action::Accelerate, GamepadButtonType::West);
+        assert_eq!(
+            map.binding(Action::CycleMerch).button,
+            Some(GamepadButtonType::RightTrigger2)
+        );
+        assert_eq!(
+            map.binding(Action::PickVehicle).button,
+            Some(GamepadButtonType::West)
+        );
+        map.bind_button(Action::Replay, GamepadButtonType::West);
+        assert_eq!(
+            map.binding(Action::PickVehicle).button,
+            Some(GamepadButtonType::RightThumb)
+        );
+        assert_eq!(
+            map.binding(Action::Accelerate).button,
+            Some(GamepadButtonType::West)
+        );
+        assert!(!shares_a_button(&map));
+    }
+
+    #[test]
+    fn rebinding_never_leaves_an_action_unbound() {
+        let mut map = InputMap::default();
+        let keys = [KeyCode::KeyW, KeyCode::KeyQ, KeyCode::KeyA, KeyCode::KeyN];
+        let buttons = [
+            GamepadButtonType::South,
+            GamepadButtonType::East,
+            GamepadButtonType::LeftThumb,
+        ];
+        for (index, action) in Action::ALL.into_iter().enumerate() {
+            map.bind_key(action, keys[index % keys.len()]);
+            map.bind_button(action, buttons[index % buttons.len()]);
+        }
+        let mut bound_keys: Vec<_> = Action::ALL
+            .into_iter()
+            .map(|action| map.binding(action).key)
+            .collect();
+        bound_keys.sort_by_key(|key| format!("{key:?}"));
+        bound_keys.dedup();
+        assert_eq!(bound_keys.len(), Action::ALL.len());
+        for action in Action::ALL {
+            assert!(map.binding(action).button.is_some(), "{action:?}");
+        }
+        assert!(!shares_a_button(&map));
+    }
+}
@@ -0,0 +1,619 @@
+//! Merch, the customers who want it, the projectiles that deliver it, or knock out cones,
+//! and the depots that restock it.
+
+use std::collections::BTreeMap;
+
+use bevy::{prelude::*, utils::HashSet};
+use serde::Deserialize;
+
+use crate::{
+    car::Car,
+    events::{CustomerLeft, Delivered, HazardKnockedOut, Restocked, ShotFired},
+    level::{CustomerMovement, Restock},
+    sprites::{attach_sprite, get_texture, set_transformation, AllSprite, Camera},
+    tick_scale,
+    track::{Hazard, Lane, Obstacle},
+    PartOfLevel, TickInput, TickSet,
+};
+
+/// How fast thrown merch falls back down, per reference tick squared
+const GRAVITY: f32 = 1.0;
+
+/// How close the car has to come before a customer starts losing patience
+const NOTICE_DISTANCE: f32 = 2000.0;
+
+/// Where a customer's speech bubble is, from the customer
+const BUBBLE_OFFSET: Vec2 = Vec2::new(100.0, 150.0);
+
+/// Scale of a full patience ring in its bubble, just big enough to go around it
+const PATIENCE_RING_SCALE: f32 = 5.0;
+
+/// How close a cruising customer's car gets to the walls where the lane narrows
+const LANE_MARGIN: f32 = 150.0;
+
+/// Shooting merch at customers, and with `render` their sprites
+pub struct DeliveryPlugin {
+    pub render: bool,
+}
+
+impl Plugin for DeliveryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                move_customers,
+                restock_at_depots,
+                detect_shoot_system,
+                projectile_update,
+                detect_projectile_hit,
+                // customers delivered to this tick are gone by now
+                lose_patience,
+            )
+                .chain()
+                .in_set(TickSet::Deliver),
+        );
+        if !self.render {
+            return;
+        }
+        app.add_systems(
+            Update,
+            (
+                setup_customer,
+                setup_projectile,
+                attach_sprite::<Depot>("depot.png", 1.0),
+                depot_draw,
+                customer_draw,
+                customer_bubble_draw,
+                patience_ring_draw,
+                projectile_draw,
+            ),
+        );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Deserialize)]
+pub enum Merch {
+    #[default]
+    Banana,
+    Coconut,
+    Pineapple,
+}
+
+impl Merch {
+    pub const ALL: [Merch; 3] = [Merch::Banana, Merch::Coconut, Merch::Pineapple];
+
+    /// Share of the vehicle's projectile speed it is thrown at
+    pub fn speed_factor(self) -> f32 {
+        match self {
+            Merch::Banana => 1.0,
+            Merch::Coconut => 0.7,
+            Merch::Pineapple => 0.85,
+        }
+    }
+
+    /// How fast it is thrown upwards, per reference tick. Bananas fly flat until they hit
+    /// something, everything else is lobbed and gone once it lands.
+    pub fn lift(self) -> f32 {
+        match self {
+            Merch::Banana => 0.0,
+            Merch::Coconut => 15.0,
+            Merch::Pineapple => 12.0,
+        }
+    }
+
+    /// What `count` of it are called
+    pub fn name(self, count: usize) -> &'static str {
+        match (self, count) {
+            (Merch::Banana, 1) => "banana",
+            (Merch::Banana, _) => "bananas",
+            (Merch::Coconut, 1) => "coconut",
+            (Merch::Coconut, _) => "coconuts",
+            (Merch::Pineapple, 1) => "pineapple",
+            (Merch::Pineapple, _) => "pineapples",
+        }
+    }
+
+    pub fn sprite(self) -> &'static str {
+        match self {
+            Merch::Banana => "banana.png",
+            Merch::Coconut => "coconut.png",
+            Merch::Pineapple => "pineapple.png",
+        }
+    }
+
+    /// The speech bubble of a customer who wants it
+    pub fn speech_sprite(self) -> &'static str {
+        match self {
+            Merch::Banana => "banana-speech.png",
+            Merch::Coconut => "coconut-speech.png",
+            Merch::Pineapple => "pineapple-speech.png",
+        }
+    }
+}
+
+#[derive(Component, Clone)]
+pub struct Customer {
+    pub pos: Vec2,
+    /// Position at the previous tick, to interpolate between ticks when drawing
+    prev_pos: Vec2,
+    /// Where the customer was placed, crossings are centered on it
+    home: Vec2,
+    pub movement: CustomerMovement,
+    /// Which way a crossing is going, 1 to the right and -1 to the left
+    heading: f32,
+    pub wants: Merch,
+    /// Seconds the customer waits once the car comes near, forever if not set
+    pub patience: Option<f32>,
+    /// Seconds waited since the car came near
+    pub waited: Option<f32>,
+}
+
+impl Customer {
+    pub fn new(pos: Vec2, wants: Merch, patience: Option<f32>, movement: CustomerMovement) -> Self {
+        Customer {
+            pos,
+            prev_pos: pos,
+            home: pos,
+            movement,
+            heading: 1.0,
+            wants,
+            patience,
+            waited: None,
+        }
+    }
+
+    /// Moves the customer's car by one tick along `lane`
+    pub fn advance(&mut self, lane: &Lane, step: f32) {
+        self.prev_pos = self.pos;
+        match self.movement {
+            CustomerMovement::Parked => {}
+            CustomerMovement::Cruise { speed } => {
+                // as far from the middle of the lane as before unless it narrows, and still at
+                // the end of the track
+                let y = self.pos.y + speed * step;
+                if let (Some(center), Some(next_center), Some(gap)) = (
+                    lane.center_at(self.pos.y),
+                    lane.center_at(y),
+                    lane.gap_at(y),
+                ) {
+                    let room = (gap - LANE_MARGIN).max(0.0);
+                    let offset = (self.pos.x - center).clamp(-room, room);
+                    self.pos = Vec2::new(next_center + offset, y);
+                }
+            }
+            CustomerMovement::Cross { range, speed } => {
+                self.pos.x += self.heading * speed * step;
+                // turn around at either side
+                let offset = self.pos.x - self.home.x;
+                if offset.abs() >= range && offset * self.heading > 0.0 {
+                    self.heading = -self.heading;
+                }
+            }
+        }
+    }
+
+    /// How much of their patience the customer has left, from 1 down to 0
+    pub fn patience_left(&self) -> f32 {
+        match (self.patience, self.waited) {
+            (Some(patience), Some(waited)) => (1.0 - waited / patience).max(0.0),
+            _ => 1.0,
+        }
+    }
+}
+
+/// The speech bubble above a customer, gone with them
+#[derive(Component)]
+struct CustomerBubble {
+    customer: Entity,
+}
+
+/// Drawn around a bubble, shrinking as the customer's patience runs out
+#[derive(Component)]
+struct PatienceRing;
+
+#[derive(Component)]
+pub struct Projectile {
+    pub pos: Vec2,
+    prev_pos: Vec2,
+    pub vel: Vec2,
+    pub merch: Merch,
+    /// How high above the track it is, and how fast that is changing
+    pub height: f32,
+    lift: f32,
+}
+
+impl Projectile {
+    /// Lobbed merch that came back down to the track, it can't hit anything anymore
+    fn landed(&self) -> bool {
+        self.height < 0.0
+    }
+}
+
+/// A pickup zone that restocks the car's merch the first time it drives through
+#[derive(Component)]
+pub struct Depot {
+    pub pos: Vec2,
+    pub radius: f32,
+    /// The car only restocks going slower than this, if set
+    pub max_speed: Option<f32>,
+    pub restock: Restock,
+    /// Depots restock only once per run
+    pub used: bool,
+}
+
+impl Depot {
+    pub fn new(pos: Vec2, radius: f32, max_speed: Option<f32>, restock: Restock) -> Self {
+        Depot {
+            pos,
+            radius,
+            max_speed,
+            restock,
+            used: false,
+        }
+    }
+
+    /// Whether `car` is in the depot, slow enough to restock
+    fn serves(&self, car: &Car) -> bool {
+        car.pos.distance(self.pos) < self.radius
+            && !self
+                .max_speed
+                .is_some_and(|max_speed| car.vel.length() > max_speed)
+    }
+
+    fn restock(&self, car: &mut Car) {
+        match &self.restock {
+            Restock::Full => {
+                for (&merch, &count) in &car.starting_ammo {
+                    let carried = car.ammo.entry(merch).or_default();
+                    *carried = (*carried).max(count);
+                }
+            }
+            Restock::Add(amounts) => {
+                for (&merch, &count) in amounts {
+                    *car.ammo.entry(merch).or_default() += count;
+                }
+            }
+        }
+    }
+}
+
+/// What a car carries when the level doesn't say otherwise
+pub(crate) fn lv1_ammo() -> BTreeMap<Merch, usize> {
+    vec![(Merch::Banana, 10)].into_iter().collect()
+}
+
+/// Moves projectiles along, and takes away lobbed ones that have landed.
+fn projectile_update(
+    mut commands: Commands,
+    mut projectiles: Query<(Entity, &mut Projectile)>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let step = tick_scale(&fixed_time);
+    for (entity, mut projectile) in &mut projectiles {
+        projectile.prev_pos = projectile.pos;
+        projectile.pos = projectile.pos + projectile.vel * step;
+        if projectile.merch.lift() > 0.0 {
+            projectile.height += projectile.lift * step;
+            projectile.lift -= GRAVITY * step;
+        }
+        if projectile.landed() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn move_customers(
+    mut customers: Query<&mut Customer>,
+    lane: Res<Lane>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let step = tick_scale(&fixed_time);
+    for mut customer in &mut customers {
+        customer.advance(&lane, step);
+    }
+}
+
+fn restock_at_depots(
+    mut depots: Query<(Entity, &mut Depot)>,
+    mut car: Query<&mut Car>,
+    mut restocked: EventWriter<Restocked>,
+) {
+    let mut car = car.single_mut();
+    for (entity, mut depot) in &mut depots {
+        if !depot.used && depot.serves(&car) {
+            depot.restock(&mut car);
+            depot.used = true;
+            restocked.send(Restocked { depot: entity });
+        }
+    }
+}
+
+fn detect_shoot_system(
+    mut input: ResMut<TickInput>,
+    mut commands: Commands,
+    mut car: Query<&mut Car>,
+    mut shots_fired: EventWriter<ShotFired>,
+) {
+    let mut car = car.single_mut();
+    if input.cycle_merch {
+        car.cycle_merch();
+        input.cycle_merch = false;
+    }
+    let merch = car.selected;
+    let shots = [
+        (input.shoot_right, -std::f32::consts::FRAC_PI_2),
+        (input.shoot_left, std::f32::consts::FRAC_PI_2),
+    ];
+    // the queued shots are used up by this tick
+    input.shoot_left = false;
+    input.shoot_right = false;
+    for (pressed, angle) in shots {
+        if car.ammo.get(&merch).unwrap_or(&0) != &0 && pressed {
+            commands.spawn((
+                Projectile {
+                    pos: car.pos,
+                    prev_pos: car.pos,
+                    // rotate direction so it shoots from right if J is pressed
+                    vel: car.direction.rotate(Vec2::from_angle(angle))
+                        * car.projectile_speed
+                        * merch.speed_factor(),
+                    merch,
+                    height: 0.0,
+                    lift: merch.lift(),
+                },
+                PartOfLevel,
+            ));
+            shots_fired.send(ShotFired { merch });
+            let _map = car.ammo.get_mut(&merch).map(|x| {
+                if *x > 0 {
+                    *x -= 1
+                }
+            });
+        }
+    }
+}
+
+/// Delivers merch to the customers it hits and knocks out the cones, and stops it at the walls.
+/// Each projectile hits one thing at most, and each customer or cone is only hit once.
+fn detect_projectile_hit(
+    mut commands: Commands,
+    projectiles: Query<(Entity, &Projectile)>,
+    customers: Query<(Entity, &Customer)>,
+    obstacles: Query<&Obstacle>,
+    hazards: Query<(Entity, &Hazard)>,
+    mut deliveries: EventWriter<Delivered>,
+    mut knockouts: EventWriter<HazardKnockedOut>,
+) {
+    let mut hit = HashSet::new();
+    // landed merch is taken away by `projectile_update`
+    let flying = projectiles
+        .iter()
+        .filter(|(_, projectile)| !projectile.landed());
+    for (projectile_entity, projectile) in flying {
+        let customer = customers.iter().find(|(entity, customer)| {
+            !hit.contains(entity)
+                && projectile.pos.distance(customer.pos) < 200.
+                && projectile.merch == customer.wants
+        });
+        if let Some((customer_entity, customer)) = customer {
+            commands.entity(projectile_entity).despawn();
+            commands.entity(customer_entity).despawn();
+            hit.insert(customer_entity);
+            deliveries.send(Delivered {
+                merch: customer.wants,
+            });
+            continue;
+        }
+
+        let hazard = hazards.iter().find(|(entity, hazard)| {
+            !hit.contains(entity) && hazard.knockable && hazard.touches(projectile.pos)
+        });
+        if let Some((hazard_entity, hazard)) = hazard {
+            commands.entity(projectile_entity).despawn();
+            commands.entity(hazard_entity).despawn();
+            hit.insert(hazard_entity);
+            knockouts.send(HazardKnockedOut {
+                hazard: hazard_entity,
+                home: hazard.home,
+            });
+            continue;
+        }
+
+        if obstacles
+            .iter()
+            .any(|obstacle| projectile.pos.distance(obstacle.pos) < 100.)
+        {
+            commands.entity(projectile_entity).despawn();
+        }
+    }
+}
+
+/// Starts customers waiting once the car comes near, and sends them away when their patience
+/// runs out.
+fn lose_patience(
+    mut commands: Commands,
+    mut customers: Query<(Entity, &mut Customer)>,
+    car: Query<&Car>,
+    fixed_time: Res<Time<Fixed>>,
+    mut left: EventWriter<CustomerLeft>,
+) {
+    let car_pos = car.single().pos;
+    for (entity, mut customer) in &mut customers {
+        let Some(patience) = customer.patience else {
+            continue;
+        };
+        let waited = match customer.waited {
+            Some(waited) => waited + fixed_time.timestep().as_secs_f32(),
+            None if customer.pos.distance(car_pos) < NOTICE_DISTANCE => 0.0,
+            None => continue,
+        };
+        customer.waited = Some(waited);
+        if waited >= patience {
+            commands.entity(entity).despawn();
+            left.send(CustomerLeft {
+                merch: customer.wants,
+            });
+        }
+    }
+}
+
+fn setup_customer(
+    mut commands: Commands,
+    customers: Query<(Entity, &Customer), Added<Customer>>,
+    sprites: Query<&AllSprite>,
+) {
+    let all_sprites = sprites.single();
+    for (entity, customer) in &customers {
+        let mut transform = Transform::from_xyz(customer.pos.x, customer.pos.y, 1.0);
+        transform.scale = Vec3::new(1.0, 1.0, 1.0) * 0.15;
+        commands.entity(entity).insert(SpriteBundle {
+            texture: get_texture(all_sprites, "banana-car.png"),
+            transform,
+            ..default()
+        });
+
+        // spawn a bubble above the car
+        let mut transform = Transform::from_xyz(customer.pos.x, customer.pos.y + 100., 3.0);
+        transform.scale = Vec3::new(1.0, 1.0, 1.0) * 0.15;
+        let mut bubble = commands.spawn((
+            SpriteBundle {
+                texture: get_texture(all_sprites, customer.wants.speech_sprite()),
+                transform,
+                ..default()
+            },
+            CustomerBubble { customer: entity },
+            PartOfLevel,
+        ));
+        if customer.patience.is_some() {
+            bubble.with_children(|parent| {
+                parent.spawn((
+                    SpriteBundle {
+                        texture: get_texture(all_sprites, "patience-ring.png"),
+                        ..default()
+                    },
+                    PatienceRing,
+                    PartOfLevel,
+                ));
+            });
+        }
+    }
+}
+
+fn customer_bubble_draw(
+    mut commands: Commands,
+    mut bubble_query: Query<(Entity, &CustomerBubble, &mut Transform)>,
+    customers: Query<&Customer>,
+    camera: Camera,
+) {
+    let view = camera.view();
+    for (entity, bubble, mut transform) in &mut bubble_query {
+        let Ok(customer) = customers.get(bubble.customer) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        let pos = customer.prev_pos.lerp(customer.pos, camera.alpha()) + BUBBLE_OFFSET;
+        set_transformation(&mut transform, &pos, 0.15, &view, Vec2::ZERO);
+        transform.translation.z = 20.0;
+    }
+}
+
+/// Shrinks the patience rings and turns them from green to red as patience runs out.
+fn patience_ring_draw(
+    mut rings: Query<(&Parent, &mut Transform, &mut Sprite), With<PatienceRing>>,
+    bubbles: Query<&CustomerBubble>,
+    customers: Query<&Customer>,
+) {
+    for (parent, mut transform, mut sprite) in &mut rings {
+        let Ok(bubble) = bubbles.get(parent.get()) else {
+            continue;
+        };
+        let Ok(customer) = customers.get(bubble.customer) else {
+            continue;
+        };
+        let left = customer.patience_left();
+        // in front of the bubble, which already sets the perspective
+        *transform =
+            Transform::from_xyz(0.0, 0.0, 1.0).with_scale(Vec3::splat(PATIENCE_RING_SCALE * left));
+        sprite.color = Color::rgb(1.0 - left, left, 0.0);
+    }
+}
+
+fn customer_draw(
+    mut customer_query: Query<(&Customer, &mut Transform)>,
+    camera: Camera,
+    assets: Res<Assets<Image>>,
+    the_allsprite: Query<&AllSprite>,
+) {
+    if let Some(sprite) = assets.get(get_texture(
+        the_allsprite.get_single().unwrap(),
+        "banana-car.png",
+    )) {
+        let view = camera.view();
+        for (customer, mut transform) in &mut customer_query {
+            let pos = customer.prev_pos.lerp(customer.pos, camera.alpha());
+            set_transformation(&mut transform, &pos, 0.1, &view, sprite.size_f32());
+            // face the way the car is driving
+            if let Some(direction) = (customer.pos - customer.prev_pos).try_normalize() {
+                transform.rotation =
+                    Quat::from_rotation_z(direction.to_angle() - std::f32::consts::FRAC_PI_2);
+            }
+        }
+    }
+}
+
+/// Gives a new projectile the sprite of its merch.
+fn setup_projectile(
+    mut commands: Commands,
+    projectiles: Query<(Entity, &Projectile), Added<Projectile>>,
+    sprites: Query<&AllSprite>,
+) {
+    for (entity, projectile) in &projectiles {
+        commands.entity(entity).insert(SpriteBundle {
+            texture: get_texture(sprites.single(), projectile.merch.sprite()),
+            transform: Transform::from_scale(Vec3::splat(0.04)),
+            ..default()
+        });
+    }
+}
+
+fn projectile_draw(
+    mut projectile_query: Query<(&Projectile, &mut Transform, &Handle<Image>)>,
+    camera: Camera,
+    assets: Res<Assets<Image>>,
+) {
+    let view = camera.view();
+    for (projectile, mut transform, texture) in &mut projectile_query {
+        let Some(sprite) = assets.get(texture) else {
+            continue;
+        };
+        let pos = projectile.prev_pos.lerp(projectile.pos, camera.alpha());
+        set_transformation(&mut transform, &pos, 0.05, &view, sprite.size_f32());
+        // lobbed merch is drawn raised off the track, by more the closer it is
+        transform.translation.y += projectile.height * transform.translation.z;
+    }
+}
+
+/// Places depots on the track, greyed out once used.
+fn depot_draw(
+    mut depot_query: Query<(&Depot, &mut Transform, &mut Handle<Image>)>,
+    camera: Camera,
+    assets: Res<Assets<Image>>,
+    the_allsprite: Query<&AllSprite>,
+) {
+    let all_sprites = the_allsprite.get_single().unwrap();
+    let view = camera.view();
+    for (depot, mut transform, mut texture) in &mut depot_query {
+        if depot.used {
+            // only swapped once, so the sprite isn't marked changed every frame
+            texture.set_if_neq(get_texture(all_sprites, "depot-used.png"));
+        }
+        let Some(sprite) = assets.get(texture.id()) else {
+            continue;
+        };
+        // as wide as the zone the car restocks in
+        let scale = depot.radius * 2.0 / sprite.size_f32().x;
+        set_transformation(&mut transform, &depot.pos, scale, &view, sprite.size_f32());
+        // flat on the track, under the car driving through
+        transform.translation.z -= 0.5;
+    }
+}
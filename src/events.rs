@@ -0,0 +1,137 @@
+//! What happened during a simulation tick.
+//!
+//! The systems that detect a hit, a shot or the goal only send an event. Scoring
+//! and ending the run react to them here at the end of the tick, and sounds and
+//! screens react to them in their own plugins.
+
+use bevy::prelude::*;
+
+use crate::{car::Car, delivery::Merch, track::CurrentLevel, AppState};
+
+/// The car bounced off a wall
+#[derive(Event, Clone, Copy, Debug)]
+pub struct WallHit {
+    /// Where the car is after bouncing
+    pub pos: Vec2,
+    /// How fast the car was going into the wall, sideways
+    pub speed: f32,
+}
+
+/// The car ran into a hazard cone and was knocked back
+#[derive(Event, Clone, Copy, Debug)]
+pub struct HazardHit {
+    pub hazard: Entity,
+}
+
+/// A thrown banana knocked a hazard out, and it is gone
+#[derive(Event, Clone, Copy, Debug)]
+pub struct HazardKnockedOut {
+    pub hazard: Entity,
+    /// Where the hazard was placed, to find it again when the run is replayed
+    pub home: Vec2,
+}
+
+/// A customer got the merch they wanted, and is gone
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Delivered {
+    pub merch: Merch,
+}
+
+/// A customer ran out of patience and left without their merch
+#[derive(Event, Clone, Copy, Debug)]
+pub struct CustomerLeft {
+    pub merch: Merch,
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ShotFired {
+    pub merch: Merch,
+}
+
+/// The car drove through a depot and restocked its merch
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Restocked {
+    pub depot: Entity,
+}
+
+/// The car crossed the goal line
+#[derive(Event, Clone, Copy, Debug)]
+pub struct GoalReached {
+    /// Customers still waiting or that left unserved, the run is only won without any
+    pub customers_left: usize,
+}
+
+/// The run is over, and the end screen is next
+#[derive(Event, Clone, Copy, Debug)]
+pub struct RunEnded {
+    pub did_win: bool,
+    /// Whether the car made it to the goal, rather than crashing
+    pub did_finish: bool,
+}
+
+/// Counts deliveries, missed customers and crashes into walls and hazards on the car, and
+/// damages it for hitting walls.
+pub(crate) fn score_events(
+    mut car: Query<&mut Car>,
+    mut walls: EventReader<WallHit>,
+    mut hazards: EventReader<HazardHit>,
+    mut deliveries: EventReader<Delivered>,
+    mut missed: EventReader<CustomerLeft>,
+) {
+    let mut car = car.single_mut();
+    for hit in walls.read() {
+        car.crashes += 1;
+        car.take_wall_damage(hit.speed);
+    }
+    car.crashes += hazards.read().count();
+    for _ in deliveries.read() {
+        car.money += 1;
+        car.delivered += 1;
+    }
+    car.missed += missed.read().count();
+}
+
+/// Ends the run on a wall or hazard in hardcore mode, a wrecked car or the goal, in that order
+/// of priority.
+pub(crate) fn decide_run_end(
+    car: Query<&Car>,
+    mut walls: EventReader<WallHit>,
+    mut hazards: EventReader<HazardHit>,
+    mut goals: EventReader<GoalReached>,
+    mut run_ended: EventWriter<RunEnded>,
+) {
+    let car = car.single();
+    // read every event, so none are left over for the next tick
+    let hit_wall = walls.read().count() > 0;
+    let hit_hazard = hazards.read().count() > 0;
+    let goal = goals.read().last().copied();
+
+    if ((hit_wall || hit_hazard) && car.hard_mode) || car.health <= 0.0 {
+        run_ended.send(RunEnded {
+            did_win: false,
+            did_finish: false,
+        });
+    } else if let Some(goal) = goal {
+        run_ended.send(RunEnded {
+            did_win: goal.customers_left == 0,
+            did_finish: true,
+        });
+    }
+}
+
+/// Moves on to the end screen once the run has ended.
+pub(crate) fn end_level(
+    car: Query<&Car>,
+    current_level: Res<CurrentLevel>,
+    mut run_ended: EventReader<RunEnded>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if let Some(ended) = run_ended.read().last() {
+        next_state.set(AppState::EndLevel {
+            level: current_level.index,
+            did_win: ended.did_win,
+            did_finish: ended.did_finish,
+            score: car.single().ticks_elapsed,
+        });
+    }
+}
@@ -0,0 +1,52 @@
+//! What the versioned RON formats have in common: levels, vehicles and the files kept in
+//! `save::Storage`.
+
+use serde::Deserialize;
+use serde_path_to_error::Segment;
+
+/// Every format starts with its version. It is read on its own first, so a file in another
+/// format is rejected for its version instead of for whichever field changed.
+#[derive(Deserialize)]
+pub(crate) struct VersionHeader {
+    pub version: u32,
+}
+
+/// Names the field at `path` like `Path` does, e.g. `placements[1].xpos`, or `.` for the
+/// whole file. The path of a missing field ends at the struct it is missing from, so the
+/// field is added from `err`.
+pub(crate) fn field_name(path: &[&Segment], err: &ron::Error) -> String {
+    let mut field = String::new();
+    for segment in path {
+        if !field.is_empty() && !matches!(segment, Segment::Seq { .. }) {
+            field.push('.');
+        }
+        field.push_str(&segment.to_string());
+    }
+    if let ron::Error::MissingStructField { field: missing, .. } = err {
+        if !field.is_empty() {
+            field.push('.');
+        }
+        field.push_str(missing);
+    }
+    if field.is_empty() {
+        field.push('.');
+    }
+    field
+}
+
+/// Parses `raw` with `parse` for the version it starts with. `parse` returns `None` for
+/// versions it can't read, `expected` is the one it writes.
+pub(crate) fn parse_versioned<T>(
+    raw: &str,
+    expected: u32,
+    parse: impl FnOnce(u32, &str) -> Option<ron::error::SpannedResult<T>>,
+) -> Result<T, String> {
+    let header: VersionHeader = ron::from_str(raw).map_err(|err| err.to_string())?;
+    match parse(header.version, raw) {
+        Some(parsed) => parsed.map_err(|err| err.to_string()),
+        None => Err(format!(
+            "unsupported version {} (expected {expected})",
+            header.version
+        )),
+    }
+}
@@ -0,0 +1,300 @@
+//! A translucent ghost car replaying the best run on the current level.
+//!
+//! The ghost's path is simulated up front from a replay, using the same car
+//! physics as the player, so it can be drawn at any tick and compared against
+//! the player at checkpoints.
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    level::Levels,
+    replay::Replay,
+    save::Storage,
+    sprites::{set_transformation, Camera},
+    tick_scale,
+    vehicle::{Vehicle, Vehicles},
+    Car, CurrentLevel, Hazard, Obstacle, PartOfLevel, HEIGHT_OF_WALL,
+};
+
+/// Split times are shown every time the car drives this far
+const CHECKPOINT_SPACING: f32 = 20. * HEIGHT_OF_WALL;
+
+/// How long a split time stays on screen, in seconds
+const SPLIT_DISPLAY_TIME: f64 = 2.0;
+
+/// Where the best replay for a level and mode is stored
+pub fn best_replay_name(level: usize, hard_mode: bool) -> String {
+    let mode = if hard_mode { "hardcore" } else { "normal" };
+    format!("best-{}-{mode}.replay.ron", level + 1)
+}
+
+/// Keeps `replay` as the level's best replay if it won faster than the stored one, or if the
+/// stored one was recorded on another version of the level.
+pub fn store_if_best(storage: &Storage, replay: &Replay) {
+    let Some(outcome) = replay.outcome.filter(|outcome| outcome.did_win) else {
+        return;
+    };
+    let seconds = |replay: &Replay, ticks: usize| ticks as f64 / replay.tick_rate;
+    let name = best_replay_name(replay.level, replay.hard_mode);
+    let is_best = !storage
+        .read(&name)
+        .and_then(|raw| Replay::parse(&raw).ok())
+        .filter(|best| best.layout == replay.layout)
+        .and_then(|best| Some(seconds(&best, best.outcome?.ticks)))
+        .is_some_and(|best| seconds(replay, outcome.ticks) >= best);
+    if is_best {
+        storage.store(&name, replay);
+    }
+}
+
+/// A ghost loaded with `--ghost <file>`, raced instead of the local best when on its level
+#[derive(Resource, Default)]
+pub struct GhostSource {
+    pub replay: Option<Replay>,
+}
+
+#[derive(Component)]
+pub struct Ghost {
+    /// Position and direction after each tick, starting before the first one
+    path: Vec<(Vec2, Vec2)>,
+    /// Index of the next checkpoint to compare split times at
+    next_checkpoint: usize,
+    /// Sprite scale of the ghost's vehicle
+    scale: f32,
+}
+
+impl Ghost {
+    /// Drives the car through `replay` on a track with `obstacles` and `hazards`, as they are
+    /// at the start of the run
+    pub fn simulate<'a>(
+        replay: &Replay,
+        vehicle: &Vehicle,
+        obstacles: impl IntoIterator<Item = &'a Obstacle> + Clone,
+        hazards: impl IntoIterator<Item = &'a Hazard>,
+        step: f32,
+    ) -> Self {
+        let mut car = Car::new();
+        car.set_vehicle(replay.vehicle, &vehicle.stats);
+        car.hard_mode = replay.hard_mode;
+        // the ghost's own cones chase the ghost, and go when the run knocked them out
+        let mut hazards: Vec<Hazard> = hazards.into_iter().cloned().collect();
+        let mut knockouts = replay.knockouts.iter().peekable();
+        let mut path = vec![(car.pos, car.direction)];
+        let ticks = replay.outcome.map_or(usize::MAX, |outcome| outcome.ticks);
+        for input in replay.ticks().take(ticks) {
+            car.drive(&input, step);
+            let hit_wall = car.bounce_off_walls(obstacles.clone(), step);
+            if let Some(speed) = hit_wall {
+                car.take_wall_damage(speed);
+            }
+            for hazard in &mut hazards {
+                hazard.advance(car.pos, step);
+            }
+            let mut hit_hazard = false;
+            for hazard in &mut hazards {
+                hit_hazard |= hazard.hit(&mut car);
+            }
+            while let Some(knockout) =
+                knockouts.next_if(|knockout| knockout.tick <= car.ticks_elapsed)
+            {
+                hazards.retain(|hazard| hazard.home.distance(knockout.home) > 1.0);
+            }
+            path.push((car.pos, car.direction));
+            // the run ended there, like `events::decide_run_end` would
+            if car.hard_mode && (hit_wall.is_some() || hit_hazard) {
+                break;
+            }
+        }
+        Ghost {
+            path,
+            next_checkpoint: 1,
+            scale: vehicle.sprites.scale,
+        }
+    }
+
+    /// The ghost `alpha` of the way from tick `tick - 1` to `tick`
+    pub fn at(&self, tick: usize, alpha: f32) -> (Vec2, Vec2) {
+        let last = self.path.len() - 1;
+        let (prev_pos, prev_dir) = self.path[tick.saturating_sub(1).min(last)];
+        let (pos, dir) = self.path[tick.min(last)];
+        (
+            prev_pos.lerp(pos, alpha),
+            prev_dir.lerp(dir, alpha).normalize_or_zero(),
+        )
+    }
+
+    /// The first tick the ghost was past `y`
+    fn tick_past(&self, y: f32) -> Option<usize> {
+        self.path.iter().position(|(pos, _)| pos.y >= y)
+    }
+}
+
+#[derive(Component)]
+pub struct SplitText {
+    /// Tick after which the split is hidden again
+    hide_after: usize,
+}
+
+/// Where the replay a run races comes from
+#[derive(SystemParam)]
+pub(crate) struct GhostReplays<'w, 's> {
+    source: Res<'w, GhostSource>,
+    storage: Res<'w, Storage>,
+    current_level: Res<'w, CurrentLevel>,
+    levels: Levels<'w>,
+    car: Query<'w, 's, &'static Car>,
+}
+
+impl GhostReplays<'_, '_> {
+    /// The replay from `--ghost` if it is on the current level, or else the level's best in
+    /// the run's mode
+    fn to_race(&self) -> Option<Replay> {
+        let level = self.current_level.index;
+        let hard_mode = self.car.single().hard_mode;
+        self.source
+            .replay
+            .clone()
+            .filter(|replay| replay.level == level)
+            .or_else(|| {
+                let raw = self.storage.read(&best_replay_name(level, hard_mode))?;
+                Replay::parse(&raw).ok()
+            })
+    }
+}
+
+/// Spawns a ghost for the run that is starting, if there is a replay to race.
+pub(crate) fn spawn_ghost(
+    mut commands: Commands,
+    replays: GhostReplays,
+    obstacles: Query<&Obstacle>,
+    hazards: Query<&Hazard>,
+    fixed_time: Res<Time<Fixed>>,
+    asset_server: Res<AssetServer>,
+    vehicles: Vehicles,
+) {
+    let Some(replay) = replays.to_race() else {
+        return;
+    };
+    let level = replays.current_level.index;
+    let on_layout = matches!(
+        replays.levels.get(level),
+        Some(current) if current.layout == replay.layout
+    );
+    if !on_layout {
+        warn!(
+            "ghost was recorded on another version of level {}",
+            level + 1
+        );
+        return;
+    }
+    let tick_rate = 1.0 / fixed_time.timestep().as_secs_f64();
+    if (replay.tick_rate - tick_rate).abs() > 1e-6 {
+        warn!(
+            "ghost was recorded at {} ticks per second, not {tick_rate}",
+            replay.tick_rate
+        );
+        return;
+    }
+
+    let Some(vehicle) = vehicles.get(replay.vehicle) else {
+        warn!(
+            "ghost drives vehicle {}, which does not exist",
+            replay.vehicle
+        );
+        return;
+    };
+
+    let ghost = Ghost::simulate(
+        &replay,
+        vehicle,
+        &obstacles,
+        &hazards,
+        tick_scale(&fixed_time),
+    );
+    let mut transform = Transform::from_xyz(0., 0., 0.);
+    transform.scale = Vec3::splat(vehicle.sprites.scale);
+    commands.spawn((
+        SpriteBundle {
+            texture: asset_server.load(vehicle.sprites.center.clone()),
+            transform,
+            sprite: Sprite {
+                color: Color::rgba(1.0, 1.0, 1.0, 0.4),
+                ..default()
+            },
+            ..default()
+        },
+        ghost,
+        PartOfLevel,
+    ));
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 50.0,
+                color: Color::WHITE,
+                ..Default::default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(15.0),
+            left: Val::Percent(45.0),
+            ..default()
+        }),
+        SplitText { hide_after: 0 },
+        PartOfLevel,
+    ));
+}
+
+/// Shows how far ahead or behind the ghost the car is when it passes a checkpoint.
+pub(crate) fn ghost_splits(
+    mut ghosts: Query<&mut Ghost>,
+    car: Query<&Car>,
+    mut split_text: Query<(&mut Text, &mut SplitText)>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let car = car.single();
+    for mut ghost in &mut ghosts {
+        let checkpoint_y = ghost.next_checkpoint as f32 * CHECKPOINT_SPACING;
+        if car.pos.y < checkpoint_y {
+            continue;
+        }
+        ghost.next_checkpoint += 1;
+        let Some(ghost_tick) = ghost.tick_past(checkpoint_y) else {
+            continue;
+        };
+        let timestep = fixed_time.timestep().as_secs_f64();
+        let delta = (car.ticks_elapsed as f64 - ghost_tick as f64) * timestep;
+        for (mut text, mut split) in &mut split_text {
+            text.sections[0].value = format!("{delta:+.2}s");
+            text.sections[0].style.color = if delta <= 0.0 {
+                Color::GREEN
+            } else {
+                Color::RED
+            };
+            split.hide_after = car.ticks_elapsed + (SPLIT_DISPLAY_TIME / timestep) as usize;
+        }
+    }
+    for (mut text, split) in &mut split_text {
+        if car.ticks_elapsed > split.hide_after {
+            text.sections[0].value.clear();
+        }
+    }
+}
+
+pub(crate) fn ghost_draw(
+    mut ghosts: Query<(&Ghost, &mut Transform)>,
+    car: Query<&Car>,
+    camera: Camera,
+) {
+    let car = car.single();
+    let alpha = camera.alpha();
+    let view = camera.view();
+    for (ghost, mut transform) in &mut ghosts {
+        let (pos, direction) = ghost.at(car.ticks_elapsed, alpha);
+        set_transformation(&mut transform, &pos, ghost.scale, &view, Vec2::ZERO);
+        transform.rotation =
+            Quat::from_rotation_z(direction.to_angle() - std::f32::consts::FRAC_PI_2);
+    }
+}
@@ -0,0 +1,429 @@
+//! On-disk level format, loaded through the `AssetServer`.
+//!
+//! A level is a RON file (`*.level.ron`) made of segments. Each segment places
+//! `blocks` pairs of walls, shifting the lane by `drift` per block and keeping
+//! the walls `gap` away from the center of the lane. Placements are spawned
+//! at the start of their segment. The level also sets the merch the car
+//! starts with.
+
+use std::collections::BTreeMap;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    ecs::system::SystemParam,
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    delivery::{lv1_ammo, Merch},
+    format::{self, VersionHeader},
+};
+
+/// Levels in campaign order. A level is unlocked by winning the one before it.
+pub const CAMPAIGN: &[&str] = &["levels/lv1.level.ron", "levels/lv2.level.ron"];
+
+/// The only level format version there is. Levels in any other are rejected.
+pub const LEVEL_FORMAT_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum Placement {
+    Customer {
+        xpos: f32,
+        /// The merch the customer has to be thrown
+        #[serde(default)]
+        wants: Merch,
+        /// Seconds the customer waits once the car comes near, forever if not set
+        #[serde(default)]
+        patience: Option<f32>,
+        #[serde(default)]
+        movement: CustomerMovement,
+    },
+    Goal {
+        xpos: f32,
+    },
+    HangryCone {
+        xpos: f32,
+        /// How close the car can get before it hits the cone
+        #[serde(default = "cone_radius")]
+        radius: f32,
+        #[serde(default)]
+        behaviour: ConeBehaviour,
+        /// Whether a thrown banana knocks the cone out
+        #[serde(default)]
+        knockable: bool,
+    },
+    /// Restocks the car's merch the first time it drives through
+    Depot {
+        xpos: f32,
+        #[serde(default = "depot_radius")]
+        radius: f32,
+        /// The car only restocks going slower than this, if set
+        #[serde(default)]
+        max_speed: Option<f32>,
+        #[serde(default)]
+        restock: Restock,
+    },
+}
+
+/// How a cone moves. Speeds are per reference tick, like the car's.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Deserialize)]
+pub enum ConeBehaviour {
+    #[default]
+    Still,
+    /// Drives back and forth across the lane, up to `range` either side of where it was placed
+    Patrol { range: f32, speed: f32 },
+    /// Chases the car while it is within `sight`
+    Chase { sight: f32, speed: f32 },
+}
+
+/// How a customer's car moves. Speeds are per reference tick, like the car's.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Deserialize)]
+pub enum CustomerMovement {
+    #[default]
+    Parked,
+    /// Drives up the track at `speed`, following the lane as far from its middle as it was
+    /// placed, or closer where the lane narrows, and stops at the end of the track
+    Cruise { speed: f32 },
+    /// Drives back and forth across the track, up to `range` either side of where it was placed
+    Cross { range: f32, speed: f32 },
+}
+
+/// What a depot gives the car
+#[derive(Clone, PartialEq, Debug, Default, Deserialize)]
+pub enum Restock {
+    /// Tops every kind of merch back up to what the car started the level with
+    #[default]
+    Full,
+    /// Adds these amounts, even of merch the car didn't carry yet
+    Add(BTreeMap<Merch, usize>),
+}
+
+fn cone_radius() -> f32 {
+    150.0
+}
+
+fn depot_radius() -> f32 {
+    300.0
+}
+
+#[derive(Clone, Debug)]
+pub struct Segment {
+    /// How many blocks of wall to render
+    pub blocks: usize,
+    /// How far the lane moves sideways per block
+    pub drift: f32,
+    /// Distance from the center of the lane to each wall
+    pub gap: f32,
+    pub placements: Vec<Placement>,
+}
+
+#[derive(Asset, TypePath, Debug)]
+pub struct Level {
+    pub name: String,
+    /// Segments in track order, with `repeat` already expanded.
+    pub segments: Vec<Segment>,
+    /// How much of each merch the car starts with
+    pub ammo: BTreeMap<Merch, usize>,
+    /// Hash of the level file, told apart from the other versions of the level a replay may
+    /// have been recorded on
+    pub layout: u64,
+}
+
+impl Level {
+    /// How many customers wait along the track
+    pub fn customers(&self) -> usize {
+        self.segments
+            .iter()
+            .flat_map(|segment| &segment.placements)
+            .filter(|placement| matches!(placement, Placement::Customer { .. }))
+            .count()
+    }
+}
+
+/// Handles to every level in `CAMPAIGN`, indexed by level number
+#[derive(Resource)]
+pub struct LevelRegistry {
+    pub levels: Vec<Handle<Level>>,
+    /// Why levels failed to load, like a `LevelLoaderError`
+    pub errors: HashMap<AssetId<Level>, String>,
+}
+
+impl LevelRegistry {
+    pub fn load(asset_server: &AssetServer) -> Self {
+        LevelRegistry {
+            levels: CAMPAIGN
+                .iter()
+                .map(|path| asset_server.load(*path))
+                .collect(),
+            errors: HashMap::default(),
+        }
+    }
+}
+
+/// The levels in `LevelRegistry`, for systems that look them up by level number
+#[derive(SystemParam)]
+pub(crate) struct Levels<'w> {
+    registry: Res<'w, LevelRegistry>,
+    assets: Res<'w, Assets<Level>>,
+}
+
+impl Levels<'_> {
+    /// The level, once it has loaded
+    pub fn get(&self, index: usize) -> Option<&Level> {
+        self.assets.get(self.registry.levels.get(index)?)
+    }
+
+    pub fn count(&self) -> usize {
+        self.registry.levels.len()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LevelFile {
+    /// Already checked through `VersionHeader`
+    #[allow(dead_code)]
+    version: u32,
+    name: String,
+    segments: Vec<SegmentFile>,
+    #[serde(default = "lv1_ammo")]
+    ammo: BTreeMap<Merch, usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SegmentFile {
+    blocks: usize,
+    drift: f32,
+    gap: f32,
+    #[serde(default)]
+    placements: Vec<Placement>,
+    /// Emit this segment several times in a row.
+    #[serde(default = "one")]
+    repeat: usize,
+    /// Added to `gap` on every repetition, used for funnels and boxes.
+    #[serde(default)]
+    gap_step: f32,
+}
+
+fn one() -> usize {
+    1
+}
+
+#[derive(Debug, Error)]
+pub enum LevelLoaderError {
+    #[error("could not read level file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("level file is not valid RON: {0}")]
+    Syntax(#[from] ron::error::SpannedError),
+    #[error("segment {segment}, field `{field}`: {message}")]
+    Segment {
+        segment: usize,
+        field: String,
+        message: String,
+    },
+    #[error("field `{field}`: {message}")]
+    Field { field: String, message: String },
+    #[error("unsupported level format version {found} (expected {LEVEL_FORMAT_VERSION})")]
+    Version { found: u32 },
+}
+
+impl LevelLoaderError {
+    fn from_path(err: serde_path_to_error::Error<ron::Error>) -> Self {
+        use serde_path_to_error::Segment as PathSegment;
+
+        let message = err.inner().to_string();
+        let path: Vec<_> = err.path().iter().collect();
+        match path.as_slice() {
+            [PathSegment::Map { key }, PathSegment::Seq { index }, rest @ ..]
+                if key == "segments" =>
+            {
+                LevelLoaderError::Segment {
+                    segment: *index,
+                    field: format::field_name(rest, err.inner()),
+                    message,
+                }
+            }
+            path => LevelLoaderError::Field {
+                field: format::field_name(path, err.inner()),
+                message,
+            },
+        }
+    }
+}
+
+/// FNV-1a, which stays the same across builds and platforms unlike `DefaultHasher`
+fn layout_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Parses the contents of a `.level.ron` file.
+pub fn parse_level(bytes: &[u8]) -> Result<Level, LevelLoaderError> {
+    // a level in another format would only fail on its fields, so check the version first
+    let mut deserializer = ron::Deserializer::from_bytes(bytes)?;
+    let header: VersionHeader =
+        serde_path_to_error::deserialize(&mut deserializer).map_err(LevelLoaderError::from_path)?;
+    if header.version != LEVEL_FORMAT_VERSION {
+        return Err(LevelLoaderError::Version {
+            found: header.version,
+        });
+    }
+
+    let mut deserializer = ron::Deserializer::from_bytes(bytes)?;
+    let file: LevelFile =
+        serde_path_to_error::deserialize(&mut deserializer).map_err(LevelLoaderError::from_path)?;
+    deserializer.end().map_err(|err| LevelLoaderError::Field {
+        field: ".".into(),
+        message: err.to_string(),
+    })?;
+
+    let mut segments = vec![];
+    for (index, segment) in file.segments.into_iter().enumerate() {
+        if segment.repeat == 0 {
+            return Err(LevelLoaderError::Segment {
+                segment: index,
+                field: "repeat".into(),
+                message: "must be at least 1".into(),
+            });
+        }
+        for i in 0..segment.repeat {
+            segments.push(Segment {
+                blocks: segment.blocks,
+                drift: segment.drift,
+                gap: segment.gap + (i as f32) * segment.gap_step,
+                // placements only belong to the first repetition
+                placements: if i == 0 {
+                    segment.placements.clone()
+                } else {
+                    vec![]
+                },
+            });
+        }
+    }
+
+    Ok(Level {
+        name: file.name,
+        segments,
+        ammo: file.ammo,
+        layout: layout_hash(bytes),
+    })
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    type Asset = Level;
+    type Settings = ();
+    type Error = LevelLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Level, LevelLoaderError>> {
+        Box::pin(async move {
+            let mut bytes = vec![];
+            reader.read_to_end(&mut bytes).await?;
+            parse_level(&bytes)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A level with a straight segment before `segment`
+    fn level_with(segment: &str) -> String {
+        format!(
+            "(version: 1, name: \"Test\", segments: [(blocks: 1, drift: 0.0, gap: 400.0), {segment}])"
+        )
+    }
+
+    fn error(raw: &str) -> LevelLoaderError {
+        parse_level(raw.as_bytes()).unwrap_err()
+    }
+
+    #[test]
+    fn a_wrong_type_names_the_segment_and_field() {
+        let err = error(&level_with("(blocks: 1, drift: \"far\", gap: 400.0)"));
+        assert!(
+            matches!(&err, LevelLoaderError::Segment { segment: 1, field, .. } if field == "drift"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn a_missing_field_is_named() {
+        let err = error(&level_with("(blocks: 1, drift: 0.0)"));
+        assert!(
+            matches!(&err, LevelLoaderError::Segment { segment: 1, field, .. } if field == "gap"),
+            "{err}"
+        );
+        let err = error(&level_with(
+            "(blocks: 1, drift: 0.0, gap: 400.0, placements: [Goal()])",
+        ));
+        assert!(
+            matches!(&err, LevelLoaderError::Segment { segment: 1, field, .. } if field == "placements[0].Goal.xpos"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn an_unknown_field_is_named() {
+        let err = error(&level_with(
+            "(blocks: 1, drift: 0.0, gap: 400.0, colour: 3)",
+        ));
+        assert!(
+            matches!(&err, LevelLoaderError::Segment { segment: 1, field, .. } if field == "colour"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn segments_repeat_at_least_once() {
+        let err = error(&level_with(
+            "(blocks: 1, drift: 0.0, gap: 400.0, repeat: 0)",
+        ));
+        assert!(
+            matches!(&err, LevelLoaderError::Segment { segment: 1, field, .. } if field == "repeat"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn other_versions_are_rejected_before_their_fields() {
+        let err = error("(version: 2, name: \"Test\", tracks: [])");
+        assert!(
+            matches!(err, LevelLoaderError::Version { found: 2 }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn any_edit_to_a_level_changes_its_layout() {
+        let raw = level_with("(blocks: 1, drift: 0.0, gap: 400.0)");
+        let layout = |raw: &str| parse_level(raw.as_bytes()).unwrap().layout;
+        assert_eq!(layout(&raw), layout(&raw));
+        assert_ne!(layout(&raw), layout(&raw.replace("400.0)]", "401.0)]")));
+    }
+
+    #[test]
+    fn the_levels_parse() {
+        for path in CAMPAIGN {
+            let raw = std::fs::read(format!("assets/{path}")).unwrap();
+            parse_level(&raw).unwrap();
+        }
+    }
+}
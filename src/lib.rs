@@ -0,0 +1,270 @@
+//! bananas NOW! Deliver bananas to every customer on the way to the goal.
+//!
+//! Every part of the game is its own plugin. The simulation half of each runs
+//! under `MinimalPlugins`, driven only by `TickInput` and `MenuInput`, so tests
+//! can play the game without a window; with `render` they also add everything
+//! the player sees and hears.
+
+use bevy::{app::PluginGroupBuilder, prelude::*};
+use replay::ReplayState;
+use save::Storage;
+
+pub mod car;
+pub mod controls;
+pub mod delivery;
+pub mod events;
+mod format;
+pub mod ghost;
+pub mod level;
+pub mod menu;
+pub mod replay;
+pub mod save;
+pub mod settings;
+pub mod sfx;
+mod sprites;
+pub mod track;
+pub mod ui;
+pub mod vehicle;
+
+pub use car::{Car, CarPlugin};
+pub use controls::ControlsPlugin;
+pub use delivery::{Customer, DeliveryPlugin, Merch, Projectile};
+pub use menu::MenuPlugin;
+pub use settings::SettingsPlugin;
+pub use sfx::SfxPlugin;
+pub use track::{CurrentLevel, Goal, Hazard, Lane, Obstacle, TrackPlugin};
+pub use ui::UiPlugin;
+
+pub const HEIGHT_OF_WALL: f32 = 160.0;
+
+/// Simulation ticks per second, unless `CorePlugin` is given another rate
+pub const DEFAULT_TICK_RATE: f64 = 60.0;
+
+/// The tick rate all the per-tick tuning values (speeds, accelerations) were made for
+const REFERENCE_TICK_RATE: f32 = 60.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, States)]
+pub enum AppState {
+    EndLevel {
+        level: usize,
+        did_win: bool,
+        did_finish: bool,
+        score: usize,
+    },
+    StartLevel(usize),
+    Game,
+    /// A run frozen mid-level, with the pause menu open
+    Paused,
+}
+
+/// The steps of a simulation tick, in order. They only run during `AppState::Game`.
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum TickSet {
+    /// Decide this tick's `TickInput`
+    Input,
+    /// Move the car and bounce it off walls
+    Drive,
+    Collide,
+    Deliver,
+    /// Check whether the car made it
+    Goal,
+    /// Score this tick's events and end the run if it is over
+    Outcome,
+}
+
+/// The game without rendering, audio or a keyboard. Needs `MinimalPlugins`.
+pub struct SimulationPlugins;
+
+impl PluginGroup for SimulationPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(CorePlugin::default())
+            .add(CarPlugin { render: false })
+            .add(TrackPlugin { render: false })
+            .add(DeliveryPlugin { render: false })
+            .add(MenuPlugin { render: false })
+    }
+}
+
+/// The whole game. Needs `DefaultPlugins`.
+pub struct GamePlugins;
+
+impl PluginGroup for GamePlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(CorePlugin::default())
+            .add(sprites::SpritesPlugin)
+            .add(SettingsPlugin)
+            .add(ControlsPlugin)
+            .add(CarPlugin { render: true })
+            .add(TrackPlugin { render: true })
+            .add(DeliveryPlugin { render: true })
+            .add(UiPlugin)
+            .add(SfxPlugin)
+            .add(MenuPlugin { render: true })
+    }
+}
+
+/// States, inputs, gameplay events, the fixed timestep, replays and where they are stored,
+/// which every other plugin relies on, and scoring and ending runs from the gameplay events.
+///
+/// Adds `AssetPlugin` if it is missing, so it works on top of `MinimalPlugins`.
+pub struct CorePlugin {
+    /// Simulation ticks per second of runs that aren't played back from a replay
+    pub tick_rate: f64,
+}
+
+impl Default for CorePlugin {
+    fn default() -> Self {
+        CorePlugin {
+            tick_rate: DEFAULT_TICK_RATE,
+        }
+    }
+}
+
+impl Plugin for CorePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<AssetPlugin>() {
+            app.add_plugins(AssetPlugin::default());
+        }
+        app.init_resource::<ReplayState>()
+            .init_resource::<Storage>();
+        let start_level = app.world.resource::<ReplayState>().starting_level();
+        app.insert_state(AppState::StartLevel(start_level))
+            .insert_resource(TickRate(self.tick_rate))
+            .insert_resource(Time::<Fixed>::from_hz(self.tick_rate))
+            .init_resource::<TickInput>()
+            .init_resource::<MenuInput>()
+            .add_event::<events::WallHit>()
+            .add_event::<events::HazardHit>()
+            .add_event::<events::HazardKnockedOut>()
+            .add_event::<events::Delivered>()
+            .add_event::<events::CustomerLeft>()
+            .add_event::<events::ShotFired>()
+            .add_event::<events::Restocked>()
+            .add_event::<events::GoalReached>()
+            .add_event::<events::RunEnded>()
+            // The simulation runs in fixed ticks in a fixed order, so it plays the same at any frame rate
+            .configure_sets(
+                FixedUpdate,
+                (
+                    TickSet::Input,
+                    TickSet::Drive,
+                    TickSet::Collide,
+                    TickSet::Deliver,
+                    TickSet::Goal,
+                    TickSet::Outcome,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Game))
+                    .run_if(run_if_no_pending_transition),
+            )
+            .add_systems(FixedUpdate, replay::replay_input.in_set(TickSet::Input))
+            .add_systems(
+                FixedUpdate,
+                replay::record_knockouts.in_set(TickSet::Outcome),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    events::score_events,
+                    events::decide_run_end,
+                    events::end_level,
+                )
+                    .chain()
+                    .in_set(TickSet::Outcome),
+            )
+            .add_systems(
+                OnEnter(AppState::Game),
+                replay::begin_run.run_if(run_if_not_resuming),
+            )
+            .add_systems(
+                Update,
+                replay::finish_run.run_if(run_if_in_end_level.and_then(state_changed::<AppState>)),
+            )
+            .add_systems(Last, clear_menu_input);
+    }
+}
+
+/// Several ticks can run in one frame, so stop simulating once the run has ended
+/// instead of waiting for the state change to be applied.
+fn run_if_no_pending_transition(next_state: Res<NextState<AppState>>) -> bool {
+    next_state.0.is_none()
+}
+
+/// For `OnEnter(AppState::Game)` systems that start a run, which must not run again on resuming
+fn run_if_not_resuming(mut transitions: EventReader<StateTransitionEvent<AppState>>) -> bool {
+    !transitions
+        .read()
+        .last()
+        .is_some_and(|transition| transition.before == AppState::Paused)
+}
+
+fn run_if_in_start_level(state: Res<State<AppState>>) -> bool {
+    matches!(state.get(), AppState::StartLevel(_))
+}
+
+fn run_if_in_end_level(state: Res<State<AppState>>) -> bool {
+    matches!(state.get(), AppState::EndLevel { .. })
+}
+
+/// The tick rate `CorePlugin` was given. `Time<Fixed>` ticks at it, except while a replay
+/// recorded at another rate is played back.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct TickRate(pub f64);
+
+// All objects part of the level need this component so they can be despawned
+#[derive(Component)]
+pub struct PartOfLevel;
+
+/// Input for the next simulation tick, gathered every frame from the keyboard and gamepads
+#[derive(Resource, Default, Clone, Copy, PartialEq, Debug)]
+pub struct TickInput {
+    /// How far the throttle is pressed, from 0 to 1
+    pub throttle: f32,
+    /// How hard to steer, from -1 (full right) to 1 (full left)
+    pub steer: f32,
+    /// How hard to brake, from 0 to 1. Braking at a standstill drives in reverse.
+    pub brake: f32,
+    /// Lets the car slide while held
+    pub handbrake: bool,
+    /// Shots stay queued until a tick consumes them, so quick taps are never lost
+    pub shoot_left: bool,
+    pub shoot_right: bool,
+    /// Select the next kind of merch, queued like the shots
+    pub cycle_merch: bool,
+}
+
+/// Menu buttons pressed this frame. They are cleared at the end of every frame.
+#[derive(Resource, Default, Clone, Copy)]
+pub struct MenuInput {
+    /// Start the level, or restart it from the end screen
+    pub start: bool,
+    /// Start the level in hardcore mode
+    pub hardcore: bool,
+    /// Pick the previous unlocked level on the start screen
+    pub select_left: bool,
+    /// Pick the next unlocked level on the start screen
+    pub select_right: bool,
+    /// Go on to the next level after winning
+    pub next_level: bool,
+    /// Watch the run that just ended
+    pub replay: bool,
+    /// Pick the menu item above
+    pub up: bool,
+    /// Pick the menu item below
+    pub down: bool,
+    /// Open or close the vehicle select screen on the start screen
+    pub vehicles: bool,
+    /// Pause the run, or resume it from the pause menu
+    pub pause: bool,
+}
+
+fn clear_menu_input(mut menu_input: ResMut<MenuInput>) {
+    *menu_input = MenuInput::default();
+}
+
+/// How much of a reference tick one simulation tick is worth
+fn tick_scale(fixed_time: &Time<Fixed>) -> f32 {
+    fixed_time.timestep().as_secs_f32() * REFERENCE_TICK_RATE
+}
@@ -0,0 +1,1145 @@
+//! The start and end screens, and moving between levels.
+
+use bevy::{audio::Volume, ecs::system::SystemParam, input::InputSystem, prelude::*};
+
+use crate::{
+    car::Car,
+    controls::{key_name, Action, Controls, InputMap},
+    level::{Level, Levels},
+    replay::{self, Playback, ReplayState, Verification},
+    run_if_in_end_level, run_if_in_start_level,
+    save::{self, GameMode, RunRecord, SaveData, Storage},
+    settings::{settings_screen_closed, Settings, SettingsScreen},
+    track::{self, CurrentLevel},
+    vehicle::{SelectedVehicle, Vehicle, VehicleStats, Vehicles},
+    AppState, MenuInput, PartOfLevel, TickInput,
+};
+
+/// Starting, ending and picking levels, and with `render` the screens and music that go with them
+pub struct MenuPlugin {
+    pub render: bool,
+}
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PauseMenu>()
+            .init_resource::<VehicleSelect>()
+            .add_systems(Startup, setup_save)
+            .add_systems(Update, check_pause.run_if(in_state(AppState::Game)))
+            .add_systems(Update, check_pause_menu.run_if(in_state(AppState::Paused)))
+            .add_systems(
+                Update,
+                record_run.run_if(run_if_in_end_level.and_then(state_changed::<AppState>)),
+            )
+            .add_systems(Update, check_start_level.run_if(run_if_in_start_level))
+            .add_systems(
+                Update,
+                check_end_to_start
+                    .after(replay::finish_run)
+                    .run_if(run_if_in_end_level),
+            );
+        if !self.render {
+            return;
+        }
+        app.add_systems(Startup, setup_music)
+            .add_systems(
+                PreUpdate,
+                gather_menu_input
+                    .after(InputSystem)
+                    .run_if(settings_screen_closed),
+            )
+            .add_systems(OnEnter(AppState::Game), start_game)
+            .add_systems(OnEnter(AppState::Paused), setup_pause_menu)
+            .add_systems(OnExit(AppState::Paused), despawn_pause_menu)
+            .add_systems(
+                Update,
+                (
+                    pause_menu_update_system,
+                    open_settings.before(check_pause_menu),
+                )
+                    .run_if(in_state(AppState::Paused)),
+            )
+            .add_systems(
+                Update,
+                apply_music_volume.run_if(resource_changed::<Settings>),
+            )
+            .add_systems(
+                Update,
+                (setup_endlevel, setup_leaderboard, setup_verification)
+                    .after(record_run)
+                    .after(replay::finish_run)
+                    .run_if(run_if_in_end_level.and_then(state_changed::<AppState>)),
+            )
+            .add_systems(
+                Update,
+                setup_start.run_if(run_if_in_start_level.and_then(state_changed::<AppState>)),
+            )
+            .add_systems(
+                Update,
+                (
+                    level_name_text_update_system,
+                    vehicle_name_text_update_system,
+                )
+                    .run_if(run_if_in_start_level),
+            )
+            .add_systems(
+                Update,
+                show_vehicle_select.run_if(resource_changed::<VehicleSelect>),
+            )
+            .add_systems(
+                Update,
+                bindings_text_update_system
+                    .after(setup_start)
+                    .run_if(run_if_in_start_level.and_then(resource_changed::<Settings>)),
+            );
+    }
+}
+
+#[derive(Component)]
+struct PartOfStart;
+
+#[derive(Component)]
+struct PartOfEndLevel;
+
+#[derive(Component)]
+struct LevelNameText;
+
+#[derive(Component)]
+struct LevelGoalText;
+
+#[derive(Component)]
+struct VehicleNameText;
+
+#[derive(Component)]
+struct PartOfPauseMenu;
+
+#[derive(Component)]
+struct PartOfVehicleSelect;
+
+/// A pause menu line, showing `PauseItem::ALL[index]`
+#[derive(Component)]
+struct PauseMenuText(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PauseItem {
+    Resume,
+    Restart,
+    Settings,
+    Quit,
+}
+
+impl PauseItem {
+    const ALL: [PauseItem; 4] = [
+        PauseItem::Resume,
+        PauseItem::Restart,
+        PauseItem::Settings,
+        PauseItem::Quit,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            PauseItem::Resume => "Resume",
+            PauseItem::Restart => "Restart level",
+            PauseItem::Settings => "Settings",
+            PauseItem::Quit => "Quit to title",
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct PauseMenu {
+    selected: usize,
+    /// Set by restarting from the pause menu, so the next run starts by itself in the same mode
+    restart_hard_mode: Option<bool>,
+}
+
+/// The vehicle select screen, opened from the start screen
+#[derive(Resource, Default)]
+struct VehicleSelect {
+    open: bool,
+    /// Index into `vehicle::GARAGE` of the vehicle picked on the screen, driven once chosen
+    selected: usize,
+}
+
+/// Leaving the current level for another, or for the same one from the start
+#[derive(SystemParam)]
+struct LevelSwitch<'w, 's> {
+    commands: Commands<'w, 's>,
+    level_entities: Query<'w, 's, Entity, With<PartOfLevel>>,
+    current_level: ResMut<'w, CurrentLevel>,
+    next_state: ResMut<'w, NextState<AppState>>,
+}
+
+impl LevelSwitch<'_, '_> {
+    fn to(&mut self, level: usize) {
+        for entity in self.level_entities.iter() {
+            self.commands.entity(entity).despawn();
+        }
+        track::load_level(
+            &mut self.commands,
+            &mut self.current_level,
+            &mut self.next_state,
+            level,
+        );
+    }
+}
+
+/// How the next run starts: in a replay being watched, restarted from the pause menu or as
+/// picked on the start screen
+#[derive(SystemParam)]
+struct NextRun<'w> {
+    replay_state: Res<'w, ReplayState>,
+    pause_menu: ResMut<'w, PauseMenu>,
+    selected_vehicle: ResMut<'w, SelectedVehicle>,
+    vehicle_select: ResMut<'w, VehicleSelect>,
+    vehicles: Vehicles<'w>,
+    settings: Option<Res<'w, Settings>>,
+}
+
+impl NextRun<'_> {
+    /// The vehicle the run drives, once it has loaded. Replays drive their own.
+    fn vehicle(&self) -> (usize, Option<&Vehicle>) {
+        let index = self
+            .replay_state
+            .playback
+            .as_ref()
+            .map_or(self.selected_vehicle.index, |playback| {
+                playback.replay.vehicle
+            });
+        (index, self.vehicles.get(index))
+    }
+
+    /// Opens, closes and works the vehicle select screen. Returns whether it is open, the
+    /// start screen waits behind it.
+    fn select_vehicle(&mut self, menu_input: &MenuInput) -> bool {
+        let starts_by_itself = self.replay_hard_mode().is_some();
+        let select = &mut *self.vehicle_select;
+        if !select.open {
+            if menu_input.vehicles && !starts_by_itself {
+                select.open = true;
+                select.selected = self.selected_vehicle.index;
+                return true;
+            }
+            return false;
+        }
+        // wrapping around
+        let count = self.vehicles.count();
+        if menu_input.up {
+            select.selected = (select.selected + count - 1) % count;
+        } else if menu_input.down {
+            select.selected = (select.selected + 1) % count;
+        }
+        if menu_input.start {
+            self.selected_vehicle.index = select.selected;
+            select.open = false;
+        } else if menu_input.vehicles {
+            // back, keeping the vehicle that was driven
+            select.open = false;
+        }
+        true
+    }
+
+    /// The mode of a run that starts by itself: a replay's or a restart's
+    fn replay_hard_mode(&self) -> Option<bool> {
+        self.replay_state
+            .playback
+            .as_ref()
+            .map(|playback| playback.replay.hard_mode)
+            .or(self.pause_menu.restart_hard_mode)
+    }
+
+    /// The mode a run starts in when it isn't picked. Without settings, as in simulations,
+    /// that is normal mode.
+    fn default_mode(&self) -> GameMode {
+        self.settings
+            .as_ref()
+            .map_or(GameMode::Normal, |settings| settings.difficulty)
+    }
+}
+
+/// Start screen text that names bound keys, rewritten when they are rebound
+#[derive(Component)]
+struct BindingsText(fn(&InputMap) -> String);
+
+fn start_text(controls: &InputMap) -> String {
+    format!("Press {} to Start", controls.describe(Action::Confirm))
+}
+
+fn hardcore_text(controls: &InputMap) -> String {
+    format!(
+        "Press {} for Hardcore Mode",
+        controls.describe(Action::Hardcore)
+    )
+}
+
+fn controls_text(controls: &InputMap) -> String {
+    let keys: Vec<_> = [
+        Action::Accelerate,
+        Action::Brake,
+        Action::SteerLeft,
+        Action::SteerRight,
+        Action::ShootLeft,
+        Action::ShootRight,
+        Action::CycleMerch,
+    ]
+    .into_iter()
+    .map(|action| key_name(controls.binding(action).key))
+    .collect();
+    format!(
+        "Controls: {} or a gamepad (Tab for settings)",
+        keys.join(", ")
+    )
+}
+
+/// "1 customer" or "`count` customers"
+fn customers_text(count: usize) -> String {
+    match count {
+        1 => "1 customer".to_string(),
+        count => format!("{count} customers"),
+    }
+}
+
+/// What the player has to do on `level`, like "Give 6 bananas and 3 coconuts to 9 customers!"
+fn goal_text(level: &Level) -> String {
+    let merch: Vec<_> = level
+        .ammo
+        .iter()
+        .filter(|(_, &count)| count > 0)
+        .map(|(merch, &count)| format!("{count} {}", merch.name(count)))
+        .collect();
+    let customers = customers_text(level.customers());
+    match merch.split_last() {
+        Some((last, [])) => format!("Give {last} to {customers}!"),
+        Some((last, rest)) => format!("Give {} and {last} to {customers}!", rest.join(", ")),
+        None => format!("Deliver to {customers}!"),
+    }
+}
+
+fn setup_save(mut commands: Commands, storage: Res<Storage>) {
+    commands.spawn((save::load(&storage),));
+}
+
+/// Marker for the background music, to tell it apart from other sounds
+#[derive(Component)]
+struct Music;
+
+fn setup_music(mut commands: Commands, asset_server: Res<AssetServer>, settings: Res<Settings>) {
+    let mut audio = AudioBundle {
+        source: asset_server.load("game_music.ogg"),
+        ..default()
+    };
+    audio.settings.paused = true;
+    audio.settings.volume = Volume::new(settings.music_volume);
+    commands.spawn((audio, Music));
+}
+
+fn apply_music_volume(settings: Res<Settings>, audio: Query<&AudioSink, With<Music>>) {
+    if let Ok(sink) = audio.get_single() {
+        sink.set_volume(settings.music_volume);
+    }
+}
+
+/// Reads the bound keys and gamepad buttons into `MenuInput`
+fn gather_menu_input(controls: Controls, mut menu_input: ResMut<MenuInput>) {
+    *menu_input = MenuInput {
+        start: controls.just_pressed(Action::Confirm),
+        hardcore: controls.just_pressed(Action::Hardcore),
+        select_left: controls.just_pressed(Action::SteerLeft)
+            || controls.just_pressed(Action::MenuLeft),
+        select_right: controls.just_pressed(Action::SteerRight)
+            || controls.just_pressed(Action::MenuRight),
+        next_level: controls.just_pressed(Action::NextLevel),
+        replay: controls.just_pressed(Action::Replay),
+        up: controls.just_pressed(Action::MenuUp),
+        down: controls.just_pressed(Action::MenuDown),
+        vehicles: controls.just_pressed(Action::PickVehicle),
+        pause: controls.just_pressed(Action::Pause),
+    };
+}
+
+/// Adds the run that just ended to the save if it was won. A replay played back was already
+/// added when it was driven.
+fn record_run(
+    state: Res<State<AppState>>,
+    mut save: Query<&mut SaveData>,
+    car: Query<&Car>,
+    fixed_time: Res<Time<Fixed>>,
+    storage: Res<Storage>,
+    replay_state: Res<ReplayState>,
+) {
+    let AppState::EndLevel {
+        level,
+        did_win: true,
+        ..
+    } = *state.get()
+    else {
+        return;
+    };
+    if replay_state.playback.is_some() {
+        return;
+    }
+    let car = car.single();
+    let mut save = save.single_mut();
+    save.runs.push(RunRecord {
+        level,
+        vehicle: car.vehicle,
+        mode: GameMode::from_hard_mode(car.hard_mode),
+        time_ms: car.time_ms(&fixed_time),
+        merch_delivered: car.delivered,
+        money: car.money,
+        crashes: car.crashes,
+        date: save::now(),
+    });
+    save::store(&storage, &save);
+}
+
+fn check_end_to_start(
+    menu_input: Res<MenuInput>,
+    state: Res<State<AppState>>,
+    mut switch: LevelSwitch,
+    levels: Levels,
+    mut replay_state: ResMut<ReplayState>,
+) {
+    let AppState::EndLevel { level, did_win, .. } = *state.get() else {
+        return;
+    };
+    let next_level = if menu_input.replay {
+        // watch the run that just ended
+        let Some(recording) = replay_state.recording.clone() else {
+            return;
+        };
+        replay_state.playback = Some(Playback::new(recording));
+        level
+    } else if menu_input.start {
+        replay_state.playback = None;
+        level
+    } else if menu_input.next_level && did_win && level + 1 < levels.count() {
+        replay_state.playback = None;
+        level + 1
+    } else {
+        return;
+    };
+
+    // the end screen itself is cleared by `setup_start`
+    switch.to(next_level);
+}
+
+fn check_start_level(
+    menu_input: Res<MenuInput>,
+    mut switch: LevelSwitch,
+    mut car: Query<&mut Car>,
+    levels: Levels,
+    save: Query<&SaveData>,
+    mut input: ResMut<TickInput>,
+    mut next_run: NextRun,
+) {
+    if next_run.select_vehicle(&menu_input) {
+        return;
+    }
+
+    // wait for the track and the vehicle to finish loading
+    let (vehicle_index, Some(vehicle)) = next_run.vehicle() else {
+        return;
+    };
+    let stats = vehicle.stats;
+    if !switch.current_level.spawned {
+        return;
+    }
+    let replay_hard_mode = next_run.replay_hard_mode();
+
+    // pick another unlocked level
+    let save = save.single();
+    let current = switch.current_level.index;
+    let selected = if menu_input.select_left {
+        (0..current).rev().find(|&i| save.is_unlocked(i))
+    } else if menu_input.select_right {
+        (current + 1..levels.count()).find(|&i| save.is_unlocked(i))
+    } else {
+        None
+    };
+    if let Some(level) = selected {
+        switch.to(level);
+        return;
+    }
+
+    if menu_input.start | menu_input.hardcore | replay_hard_mode.is_some() {
+        switch.next_state.set(AppState::Game);
+        next_run.pause_menu.restart_hard_mode = None;
+        // set car start time
+        let mut car = car.single_mut();
+        car.ticks_elapsed = 0;
+        // drop shots queued on the start screen
+        *input = TickInput::default();
+        car.hard_mode = replay_hard_mode
+            .unwrap_or(menu_input.hardcore || next_run.default_mode() == GameMode::Hardcore);
+        car.set_vehicle(vehicle_index, &stats);
+        if let Some(level) = levels.get(current) {
+            car.load_ammo(level.ammo.clone());
+        }
+    }
+}
+
+fn check_pause(menu_input: Res<MenuInput>, mut next_state: ResMut<NextState<AppState>>) {
+    if menu_input.pause {
+        next_state.set(AppState::Paused);
+    }
+}
+
+fn check_pause_menu(
+    menu_input: Res<MenuInput>,
+    mut pause_menu: ResMut<PauseMenu>,
+    mut switch: LevelSwitch,
+    mut replay_state: ResMut<ReplayState>,
+    car: Query<&Car>,
+) {
+    if menu_input.pause {
+        switch.next_state.set(AppState::Game);
+        return;
+    }
+    let count = PauseItem::ALL.len();
+    if menu_input.up {
+        pause_menu.selected = (pause_menu.selected + count - 1) % count;
+    } else if menu_input.down {
+        pause_menu.selected = (pause_menu.selected + 1) % count;
+    }
+    if !menu_input.start {
+        return;
+    }
+    match PauseItem::ALL[pause_menu.selected] {
+        PauseItem::Resume => {
+            switch.next_state.set(AppState::Game);
+            return;
+        }
+        // opened by `open_settings`, the run stays paused behind it
+        PauseItem::Settings => return,
+        // a replay being watched starts over too
+        PauseItem::Restart => pause_menu.restart_hard_mode = Some(car.single().hard_mode),
+        PauseItem::Quit => replay_state.playback = None,
+    }
+    let level = switch.current_level.index;
+    switch.to(level);
+}
+
+/// Shows the end screen once a run is over, along with `setup_leaderboard` and
+/// `setup_verification`.
+fn setup_endlevel(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    car: Query<&Car>,
+    fixed_time: Res<Time<Fixed>>,
+    audio: Query<&AudioSink, With<Music>>,
+    levels: Levels,
+    settings: Res<Settings>,
+) {
+    let AppState::EndLevel {
+        level,
+        did_win,
+        did_finish,
+        ..
+    } = *state.get()
+    else {
+        return;
+    };
+    let car = car.single();
+    let text = if did_win {
+        format!(
+            "You won in {}!",
+            save::format_time(car.time_ms(&fixed_time))
+        )
+    } else if did_finish && car.missed > 0 {
+        format!(
+            "You lost! {} got tired of waiting!",
+            customers_text(car.missed)
+        )
+    } else if did_finish {
+        let customers = levels.get(level).map_or(0, Level::customers);
+        format!(
+            "You lost! You didn't deliver to all {}!",
+            customers_text(customers)
+        )
+    } else {
+        if let Ok(sink) = audio.get_single() {
+            sink.pause();
+        }
+        "You lost! You crashed!".to_string()
+    };
+
+    // add a text component
+    commands.spawn((
+        // Create a TextBundle that has a Text with a single section.
+        TextBundle::from_section(
+            text,
+            TextStyle {
+                font_size: 50.0,
+                color: Color::GOLD,
+                ..Default::default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(50.0),
+            left: Val::Percent(20.0),
+            ..default()
+        }),
+        PartOfEndLevel,
+    ));
+
+    // add a text component "Press space to restart"
+    let key = |action| key_name(settings.controls.binding(action).key);
+    let restart_text = if did_win && level + 1 < levels.count() {
+        format!(
+            "Press {} to Restart, {} for Next Level, {} for Replay",
+            key(Action::Confirm),
+            key(Action::NextLevel),
+            key(Action::Replay)
+        )
+    } else {
+        format!(
+            "Press {} to Restart, {} for Replay",
+            key(Action::Confirm),
+            key(Action::Replay)
+        )
+    };
+    commands.spawn((
+        // Create a TextBundle that has a Text with a single section.
+        TextBundle::from_section(
+            restart_text,
+            TextStyle {
+                font_size: 50.0,
+                color: Color::GOLD,
+                ..Default::default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(60.0),
+            left: Val::Percent(20.0),
+            ..default()
+        }),
+        PartOfEndLevel,
+    ));
+}
+
+/// Shows the best runs on the level that just ended. The run is already in the save by then.
+fn setup_leaderboard(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    save: Query<&SaveData>,
+    car: Query<&Car>,
+    vehicles: Vehicles,
+) {
+    let AppState::EndLevel { level, .. } = *state.get() else {
+        return;
+    };
+    let car = car.single();
+    let mode = GameMode::from_hard_mode(car.hard_mode);
+    // Add text component that shows best 5 scores
+    let mode_name = match mode {
+        GameMode::Normal => "",
+        GameMode::Hardcore => " (Hardcore)",
+    };
+    let vehicle_name = vehicles
+        .get(car.vehicle)
+        .map_or("", |vehicle| vehicle.name.as_str());
+    let mut text = format!(
+        "Level {}{} Best Scores, {}:\n",
+        level + 1,
+        mode_name,
+        vehicle_name
+    );
+    for run in save
+        .single()
+        .leaderboard(level, mode, car.vehicle)
+        .iter()
+        .take(5)
+    {
+        text.push_str(&format!(
+            "{}  ${}  {} crashes\n",
+            save::format_time(run.time_ms),
+            run.money,
+            run.crashes
+        ));
+    }
+    commands.spawn((
+        // Create a TextBundle that has a Text with a single section.
+        TextBundle::from_section(
+            text,
+            TextStyle {
+                font_size: 50.0,
+                color: Color::GOLD,
+                ..Default::default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(30.0),
+            left: Val::Percent(20.0),
+            ..default()
+        }),
+        PartOfEndLevel,
+    ));
+}
+
+/// Says whether the run that just ended was played back as recorded.
+fn setup_verification(mut commands: Commands, replay_state: Res<ReplayState>) {
+    if let Some(verification) = replay_state.verification {
+        let text = match verification {
+            Verification::Verified => "Replay verified",
+            Verification::Diverged => "Replay diverged from the recorded run!",
+            Verification::Unknown => "Replay has no recorded result",
+        };
+        commands.spawn((
+            TextBundle::from_section(
+                text,
+                TextStyle {
+                    font_size: 30.0,
+                    color: Color::GOLD,
+                    ..Default::default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(70.0),
+                left: Val::Percent(20.0),
+                ..default()
+            }),
+            PartOfEndLevel,
+        ));
+    }
+}
+
+/// Shows the start screen in place of the end screen, unless it is already up.
+fn setup_start(
+    mut commands: Commands,
+    end_screen: Query<Entity, With<PartOfEndLevel>>,
+    start_screen: Query<(), With<PartOfStart>>,
+    settings: Res<Settings>,
+) {
+    for entity in &end_screen {
+        commands.entity(entity).despawn();
+    }
+    if !start_screen.is_empty() {
+        return;
+    }
+
+    // make text "press space to start"
+    commands.spawn((
+        // Create a TextBundle that has a Text with a single section.
+        TextBundle::from_section(
+            start_text(&settings.controls),
+            TextStyle {
+                font_size: 50.0,
+                color: Color::GOLD,
+                ..Default::default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(50.0),
+            left: Val::Percent(20.0),
+            ..default()
+        }),
+        PartOfStart,
+        BindingsText(start_text),
+    ));
+
+    // make text "press h for hardcore mode"
+    commands.spawn((
+        // Create a TextBundle that has a Text with a single section.
+        TextBundle::from_section(
+            hardcore_text(&settings.controls),
+            TextStyle {
+                font_size: 50.0,
+                color: Color::GOLD,
+                ..Default::default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(60.0),
+            left: Val::Percent(20.0),
+            ..default()
+        }),
+        PartOfStart,
+        BindingsText(hardcore_text),
+    ));
+
+    // filled in by `level_name_text_update_system` once the level is loaded, with what the
+    // player has to do like "give 10 bananas to 10 customers!"
+    commands.spawn((
+        // Create a TextBundle that has a Text with a single section.
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 50.0,
+                color: Color::GOLD,
+                ..Default::default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(40.0),
+            left: Val::Percent(20.0),
+            ..default()
+        }),
+        LevelGoalText,
+        PartOfStart,
+    ));
+
+    // make text that says "use J and K to shoot bananas"
+    commands.spawn((
+        // Create a TextBundle that has a Text with a single section.
+        TextBundle::from_section(
+            controls_text(&settings.controls),
+            TextStyle {
+                font_size: 50.0,
+                color: Color::GOLD,
+                ..Default::default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(30.0),
+            left: Val::Percent(20.0),
+            ..default()
+        }),
+        PartOfStart,
+        BindingsText(controls_text),
+    ));
+
+    // make text that says "everything MUST GO!"
+    commands.spawn((
+        // Create a TextBundle that has a Text with a single section.
+        TextBundle::from_section(
+            "bananas NOW!",
+            TextStyle {
+                font_size: 50.0,
+                color: Color::GOLD,
+                ..Default::default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(20.0),
+            left: Val::Percent(20.0),
+            ..default()
+        }),
+        PartOfStart,
+    ));
+
+    // filled in by `level_name_text_update_system` once the level is loaded
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 50.0,
+                color: Color::GOLD,
+                ..Default::default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(70.0),
+            left: Val::Percent(20.0),
+            ..default()
+        }),
+        LevelNameText,
+        PartOfStart,
+    ));
+
+    // filled in by `vehicle_name_text_update_system` once the vehicle is loaded
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 50.0,
+                color: Color::GOLD,
+                ..Default::default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(80.0),
+            left: Val::Percent(20.0),
+            ..default()
+        }),
+        VehicleNameText,
+        PartOfStart,
+    ));
+}
+
+/// Clears the start screen and starts the music when a run begins or is resumed.
+fn start_game(
+    mut commands: Commands,
+    start_screen: Query<Entity, With<PartOfStart>>,
+    audio: Query<&AudioSink, With<Music>>,
+) {
+    if let Ok(sink) = audio.get_single() {
+        sink.play();
+    }
+    for entity in &start_screen {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn bindings_text_update_system(
+    mut query: Query<(&mut Text, &BindingsText)>,
+    settings: Res<Settings>,
+) {
+    for (mut text, bindings_text) in &mut query {
+        text.sections[0].value = (bindings_text.0)(&settings.controls);
+    }
+}
+
+/// Names the level on the start screen and says what the player has to do on it
+fn level_name_text_update_system(
+    mut name_texts: Query<&mut Text, (With<LevelNameText>, Without<LevelGoalText>)>,
+    mut goal_texts: Query<&mut Text, With<LevelGoalText>>,
+    current_level: Res<CurrentLevel>,
+    levels: Levels,
+    save: Query<&SaveData>,
+    settings: Res<Settings>,
+) {
+    let index = current_level.index;
+    let level = levels.get(index);
+    let name = match (&current_level.error, level) {
+        (Some(error), _) => format!("could not be loaded: {error}"),
+        (None, Some(level)) => level.name.clone(),
+        (None, None) => "Loading...".to_string(),
+    };
+    for mut text in &mut goal_texts {
+        text.sections[0].value = level.map_or_else(String::new, goal_text);
+    }
+    let save = save.single();
+    let key = |action| key_name(settings.controls.binding(action).key);
+    let mut arrows = String::new();
+    if (0..index).any(|i| save.is_unlocked(i)) {
+        arrows.push_str(&format!("  < {}", key(Action::MenuLeft)));
+    }
+    if (index + 1..levels.count()).any(|i| save.is_unlocked(i)) {
+        arrows.push_str(&format!("  {} >", key(Action::MenuRight)));
+    }
+    for mut text in &mut name_texts {
+        text.sections[0].value = format!("Level {}: {}{}", index + 1, name, arrows);
+    }
+}
+
+fn vehicle_name_text_update_system(
+    mut query: Query<&mut Text, With<VehicleNameText>>,
+    selected_vehicle: Res<SelectedVehicle>,
+    vehicles: Vehicles,
+    settings: Res<Settings>,
+) {
+    let name = vehicles
+        .get(selected_vehicle.index)
+        .map_or("Loading...", |vehicle| vehicle.name.as_str());
+    let arrows = if vehicles.count() > 1 {
+        format!(
+            "  {} to change",
+            key_name(settings.controls.binding(Action::PickVehicle).key)
+        )
+    } else {
+        String::new()
+    };
+    for mut text in &mut query {
+        text.sections[0].value = format!("Vehicle: {name}{arrows}");
+    }
+}
+
+/// A vehicle's stats on the select screen, compared to the `reference` vehicle's
+fn stats_text(stats: &VehicleStats, reference: &VehicleStats) -> String {
+    let percent = |value: f32, reference: f32| format!("{:.0}%", value / reference * 100.0);
+    format!(
+        "speed {}, acceleration {}, steering {}, throws {}",
+        percent(stats.top_speed, reference.top_speed),
+        percent(stats.base_acc, reference.base_acc),
+        percent(stats.steer_strength, reference.steer_strength),
+        percent(stats.projectile_speed, reference.projectile_speed),
+    )
+}
+
+/// Rebuilds the vehicle select screen whenever it changes, with every vehicle's sprite and
+/// how it drives.
+fn show_vehicle_select(
+    mut commands: Commands,
+    select: Res<VehicleSelect>,
+    vehicles: Vehicles,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+    old: Query<Entity, With<PartOfVehicleSelect>>,
+) {
+    for entity in &old {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !select.open {
+        return;
+    }
+
+    let style = TextStyle {
+        font_size: 40.0,
+        color: Color::GOLD,
+        ..default()
+    };
+    let key = |action| key_name(settings.controls.binding(action).key);
+    let help = format!(
+        "{}/{}: pick, {}: drive it, {}: back",
+        key(Action::MenuUp),
+        key(Action::MenuDown),
+        key(Action::Confirm),
+        key(Action::PickVehicle)
+    );
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    padding: UiRect::left(Val::Percent(10.0)),
+                    ..default()
+                },
+                background_color: Color::BLACK.into(),
+                // in front of the start screen
+                z_index: ZIndex::Global(5),
+                ..default()
+            },
+            PartOfVehicleSelect,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("Pick a vehicle", style.clone()));
+            for index in 0..vehicles.count() {
+                let cursor = if index == select.selected { "> " } else { "  " };
+                // compared to the first vehicle, the original racecar
+                let (Some(vehicle), Some(racecar)) = (vehicles.get(index), vehicles.get(0)) else {
+                    parent.spawn(TextBundle::from_section(
+                        format!("{cursor}Loading..."),
+                        style.clone(),
+                    ));
+                    continue;
+                };
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(20.0),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        row.spawn(TextBundle::from_section(cursor, style.clone()));
+                        // every vehicle is shown as tall, whatever its sprite's scale
+                        row.spawn(ImageBundle {
+                            image: asset_server.load(vehicle.sprites.center.clone()).into(),
+                            style: Style {
+                                height: Val::Px(100.0),
+                                ..default()
+                            },
+                            ..default()
+                        });
+                        row.spawn(TextBundle::from_section(
+                            format!(
+                                "{}: {}",
+                                vehicle.name,
+                                stats_text(&vehicle.stats, &racecar.stats)
+                            ),
+                            style.clone(),
+                        ));
+                    });
+            }
+            parent.spawn(TextBundle::from_section(help, style.clone()));
+        });
+}
+
+/// Opens the menu over the frozen run and pauses the music.
+fn setup_pause_menu(
+    mut commands: Commands,
+    mut pause_menu: ResMut<PauseMenu>,
+    audio: Query<&AudioSink, With<Music>>,
+) {
+    if let Ok(sink) = audio.get_single() {
+        sink.pause();
+    }
+    pause_menu.selected = 0;
+
+    commands.spawn((
+        TextBundle::from_section(
+            "Paused",
+            TextStyle {
+                font_size: 50.0,
+                color: Color::GOLD,
+                ..Default::default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(30.0),
+            left: Val::Percent(40.0),
+            ..default()
+        }),
+        PartOfPauseMenu,
+    ));
+    for index in 0..PauseItem::ALL.len() {
+        // filled in by `pause_menu_update_system`
+        commands.spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 50.0,
+                    color: Color::GOLD,
+                    ..Default::default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(40.0 + 8.0 * index as f32),
+                left: Val::Percent(40.0),
+                ..default()
+            }),
+            PauseMenuText(index),
+            PartOfPauseMenu,
+        ));
+    }
+}
+
+fn despawn_pause_menu(mut commands: Commands, pause_menu: Query<Entity, With<PartOfPauseMenu>>) {
+    for entity in &pause_menu {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn pause_menu_update_system(
+    mut query: Query<(&mut Text, &PauseMenuText)>,
+    pause_menu: Res<PauseMenu>,
+) {
+    for (mut text, item) in &mut query {
+        let cursor = if item.0 == pause_menu.selected {
+            "> "
+        } else {
+            "  "
+        };
+        text.sections[0].value = format!("{cursor}{}", PauseItem::ALL[item.0].label());
+    }
+}
+
+fn open_settings(
+    menu_input: Res<MenuInput>,
+    pause_menu: Res<PauseMenu>,
+    mut settings_screen: ResMut<SettingsScreen>,
+) {
+    if menu_input.start && PauseItem::ALL[pause_menu.selected] == PauseItem::Settings {
+        settings_screen.open = true;
+    }
+}
@@ -0,0 +1,341 @@
+//! Recording and replaying runs.
+//!
+//! Every simulation tick of `AppState::Game` consumes one `TickInput`. The
+//! simulation only depends on those inputs and the fixed timestep, so playing
+//! the same inputs back on the same level reproduces the run exactly.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::HazardKnockedOut, format, level::Levels, save::Storage, AppState, Car, TickInput,
+    TickRate,
+};
+
+/// The only replay format version there is. Replays in any other are rejected.
+pub const REPLAY_VERSION: u32 = 1;
+
+/// The replay of the most recent run, kept around to attach to bug reports
+pub const LAST_REPLAY_NAME: &str = "last.replay.ron";
+
+const SHOOT_LEFT: u8 = 1 << 0;
+const SHOOT_RIGHT: u8 = 1 << 1;
+const HANDBRAKE: u8 = 1 << 2;
+const CYCLE_MERCH: u8 = 1 << 3;
+
+/// One tick of input as stored: button bits, throttle in 255ths, steering in 127ths
+/// and brake in 255ths
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct PackedInput(u8, u8, i8, u8);
+
+impl PackedInput {
+    fn pack(input: &TickInput) -> Self {
+        let mut bits = 0;
+        if input.shoot_left {
+            bits |= SHOOT_LEFT;
+        }
+        if input.shoot_right {
+            bits |= SHOOT_RIGHT;
+        }
+        if input.handbrake {
+            bits |= HANDBRAKE;
+        }
+        if input.cycle_merch {
+            bits |= CYCLE_MERCH;
+        }
+        PackedInput(
+            bits,
+            (input.throttle.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (input.steer.clamp(-1.0, 1.0) * 127.0).round() as i8,
+            (input.brake.clamp(0.0, 1.0) * 255.0).round() as u8,
+        )
+    }
+
+    fn unpack(self) -> TickInput {
+        let PackedInput(bits, throttle, steer, brake) = self;
+        TickInput {
+            throttle: throttle as f32 / 255.0,
+            steer: steer as f32 / 127.0,
+            brake: brake as f32 / 255.0,
+            handbrake: bits & HANDBRAKE != 0,
+            shoot_left: bits & SHOOT_LEFT != 0,
+            shoot_right: bits & SHOOT_RIGHT != 0,
+            cycle_merch: bits & CYCLE_MERCH != 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ReplayOutcome {
+    pub did_win: bool,
+    pub did_finish: bool,
+    pub ticks: usize,
+}
+
+/// A hazard knocked out during a run. The ghost doesn't throw anything, so it takes the hazard
+/// off its track at the same tick instead.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Knockout {
+    /// `Car::ticks_elapsed` of the tick the hazard was knocked out in
+    pub tick: usize,
+    /// Where the hazard was placed
+    pub home: Vec2,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub level: usize,
+    /// `Level::layout` of the level as it was recorded on, the run plays out differently on
+    /// any other
+    pub layout: u64,
+    /// Index into `vehicle::GARAGE`
+    pub vehicle: usize,
+    pub hard_mode: bool,
+    pub tick_rate: f64,
+    /// Run-length encoded inputs: (number of ticks, input)
+    pub inputs: Vec<(u32, PackedInput)>,
+    /// How the run ended, to check that playing it back gives the same result
+    pub outcome: Option<ReplayOutcome>,
+    /// Hazards knocked out during the run, in order
+    pub knockouts: Vec<Knockout>,
+}
+
+impl Replay {
+    pub fn new(level: usize, layout: u64, vehicle: usize, hard_mode: bool, tick_rate: f64) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            level,
+            layout,
+            vehicle,
+            hard_mode,
+            tick_rate,
+            inputs: vec![],
+            outcome: None,
+            knockouts: vec![],
+        }
+    }
+
+    pub fn push(&mut self, input: &TickInput) {
+        let packed = PackedInput::pack(input);
+        match self.inputs.last_mut() {
+            Some((count, last)) if *last == packed => *count += 1,
+            _ => self.inputs.push((1, packed)),
+        }
+    }
+
+    /// The input for every tick, in order
+    pub fn ticks(&self) -> impl Iterator<Item = TickInput> + '_ {
+        self.inputs
+            .iter()
+            .flat_map(|&(count, packed)| (0..count).map(move |_| packed.unpack()))
+    }
+
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let replay = format::parse_versioned(raw, REPLAY_VERSION, |version, raw| {
+            (version == REPLAY_VERSION).then(|| ron::from_str::<Replay>(raw))
+        })?;
+        if !(replay.tick_rate.is_finite() && replay.tick_rate > 0.0) {
+            return Err(format!("invalid tick rate {}", replay.tick_rate));
+        }
+        Ok(replay)
+    }
+}
+
+/// A replay driving the car instead of the keyboard
+pub struct Playback {
+    pub replay: Replay,
+    inputs: Vec<TickInput>,
+    tick: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Playback {
+            inputs: replay.ticks().collect(),
+            replay,
+            tick: 0,
+        }
+    }
+}
+
+/// Whether playing a replay back gave the result it recorded
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Verification {
+    Verified,
+    Diverged,
+    /// The replay was saved before its run ended
+    Unknown,
+}
+
+#[derive(Resource, Default)]
+pub struct ReplayState {
+    /// Inputs of the current run, or of the last one once it is over
+    pub recording: Option<Replay>,
+    /// Set when the next run should be played back from a replay
+    pub playback: Option<Playback>,
+    /// How the last played back run compared to its replay
+    pub verification: Option<Verification>,
+}
+
+impl ReplayState {
+    /// The level the game starts on: a replay from the command line starts on its own
+    pub fn starting_level(&self) -> usize {
+        self.playback
+            .as_ref()
+            .map_or(0, |playback| playback.replay.level)
+    }
+
+    /// The vehicle picked when the game starts: a replay from the command line drives its own
+    pub fn starting_vehicle(&self) -> usize {
+        self.playback
+            .as_ref()
+            .map_or(0, |playback| playback.replay.vehicle)
+    }
+}
+
+/// Reads a replay file given on the command line as `<flag> <file>`. This runs before the app
+/// and its logging exist, so a file that can't be used is reported on stderr.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn from_arg(flag: &str) -> Option<Replay> {
+    let mut args = std::env::args().skip_while(|arg| arg != flag);
+    let path = args.nth(1)?;
+    match std::fs::read_to_string(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|raw| Replay::parse(&raw))
+    {
+        Ok(replay) => Some(replay),
+        Err(err) => {
+            eprintln!("could not load replay {path}: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn from_arg(_flag: &str) -> Option<Replay> {
+    None
+}
+
+/// Starts recording, and switches to the replay's tick rate when playing one back.
+pub(crate) fn begin_run(
+    mut replay_state: ResMut<ReplayState>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    configured: Res<TickRate>,
+    car: Query<&Car>,
+    current_level: Res<crate::CurrentLevel>,
+    levels: Levels,
+) {
+    let layout = levels
+        .get(current_level.index)
+        .map_or(0, |level| level.layout);
+    let tick_rate = match &mut replay_state.playback {
+        Some(playback) => {
+            playback.tick = 0;
+            if playback.replay.layout != layout {
+                warn!(
+                    "replay was recorded on another version of level {}, it will not play out the same",
+                    current_level.index + 1
+                );
+            }
+            playback.replay.tick_rate
+        }
+        None => configured.0,
+    };
+    fixed_time.set_timestep_hz(tick_rate);
+    replay_state.verification = None;
+    let car = car.single();
+    replay_state.recording = Some(Replay::new(
+        current_level.index,
+        layout,
+        car.vehicle,
+        car.hard_mode,
+        tick_rate,
+    ));
+}
+
+/// Runs first every tick: feeds the playback into `TickInput` and records what the tick will use.
+pub fn replay_input(mut replay_state: ResMut<ReplayState>, mut input: ResMut<TickInput>) {
+    let ReplayState {
+        recording,
+        playback,
+        ..
+    } = &mut *replay_state;
+    if let Some(playback) = playback {
+        *input = playback
+            .inputs
+            .get(playback.tick)
+            .copied()
+            .unwrap_or_default();
+        playback.tick += 1;
+    } else {
+        // analog input is stored with less precision, so play with exactly what gets recorded
+        *input = PackedInput::pack(&input).unpack();
+    }
+    if let Some(recording) = recording {
+        recording.push(&input);
+    }
+}
+
+/// Records the hazards knocked out this tick, for the ghost.
+pub fn record_knockouts(
+    mut replay_state: ResMut<ReplayState>,
+    car: Query<&Car>,
+    mut knockouts: EventReader<HazardKnockedOut>,
+) {
+    let tick = car.single().ticks_elapsed;
+    if let Some(recording) = &mut replay_state.recording {
+        recording
+            .knockouts
+            .extend(knockouts.read().map(|knockout| Knockout {
+                tick,
+                home: knockout.home,
+            }));
+    }
+}
+
+/// Stores the outcome of the run that just ended and checks it against the replay being played.
+/// A played back run is someone else's, or was stored already, so it is not stored again.
+pub fn finish_run(
+    state: Res<State<AppState>>,
+    mut replay_state: ResMut<ReplayState>,
+    storage: Res<Storage>,
+) {
+    let AppState::EndLevel {
+        did_win,
+        did_finish,
+        score,
+        ..
+    } = *state.get()
+    else {
+        return;
+    };
+    let outcome = ReplayOutcome {
+        did_win,
+        did_finish,
+        ticks: score,
+    };
+    let ReplayState {
+        recording: Some(recording),
+        playback,
+        verification,
+    } = &mut *replay_state
+    else {
+        return;
+    };
+    recording.outcome = Some(outcome);
+
+    let Some(playback) = playback else {
+        storage.store(LAST_REPLAY_NAME, recording);
+        crate::ghost::store_if_best(&storage, recording);
+        return;
+    };
+    *verification = Some(match playback.replay.outcome {
+        Some(expected) if expected == outcome => Verification::Verified,
+        Some(expected) => {
+            warn!("replay diverged: expected {expected:?}, got {outcome:?}");
+            Verification::Diverged
+        }
+        None => Verification::Unknown,
+    });
+}
@@ -0,0 +1,384 @@
+//! Persistent save data.
+//!
+//! Saves are RON, wrapped in a `SaveFile` with a schema version. Native builds
+//! keep them in a file in the user's data directory, web builds keep them in
+//! `localStorage`. A save that can't be read is set aside and replaced by an
+//! empty one instead of crashing the game.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::format;
+
+pub use storage::Storage;
+
+const SAVE_NAME: &str = "save.ron";
+
+/// The only save version there is. Saves in any other are set aside like unreadable ones.
+pub const SAVE_VERSION: u32 = 1;
+
+#[derive(Component, Default, Serialize, Deserialize)]
+pub struct SaveData {
+    /// Every winning run, in the order they were driven
+    pub runs: Vec<RunRecord>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum GameMode {
+    Normal,
+    Hardcore,
+}
+
+impl GameMode {
+    pub fn from_hard_mode(hard_mode: bool) -> Self {
+        if hard_mode {
+            GameMode::Hardcore
+        } else {
+            GameMode::Normal
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub level: usize,
+    /// Index into `vehicle::GARAGE`
+    pub vehicle: usize,
+    pub mode: GameMode,
+    /// Time to reach the goal in milliseconds
+    pub time_ms: u64,
+    /// Merch of every kind delivered, saves from before there were other kinds call it bananas
+    #[serde(alias = "bananas_delivered")]
+    pub merch_delivered: usize,
+    pub money: usize,
+    pub crashes: usize,
+    /// When the run was finished, in seconds since the unix epoch
+    pub date: u64,
+}
+
+impl SaveData {
+    /// The first level is always open, the others need the previous level beaten in any mode.
+    pub fn is_unlocked(&self, level: usize) -> bool {
+        level == 0 || self.runs.iter().any(|run| run.level == level - 1)
+    }
+
+    /// Runs on `level` in `mode` with `vehicle`, fastest first
+    pub fn leaderboard(&self, level: usize, mode: GameMode, vehicle: usize) -> Vec<&RunRecord> {
+        let mut board: Vec<_> = self
+            .runs
+            .iter()
+            .filter(|run| run.level == level && run.mode == mode && run.vehicle == vehicle)
+            .collect();
+        // earlier runs win ties
+        board.sort_by_key(|run| (run.time_ms, run.date));
+        board
+    }
+}
+
+/// Formats a run time as mm:ss.cc
+pub fn format_time(time_ms: u64) -> String {
+    let centis = time_ms / 10;
+    format!(
+        "{:02}:{:02}.{:02}",
+        centis / 6000,
+        (centis / 100) % 60,
+        centis % 100
+    )
+}
+
+/// Seconds since the unix epoch, for dating runs
+pub fn now() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        (js_sys::Date::now() / 1000.0) as u64
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs())
+    }
+}
+
+#[derive(Deserialize)]
+struct SaveFile {
+    data: SaveData,
+}
+
+/// Reads the save, falling back to an empty one if it is missing or unreadable.
+pub fn load(storage: &Storage) -> SaveData {
+    storage
+        .load(SAVE_NAME, SAVE_VERSION, |version, raw| {
+            (version == SAVE_VERSION).then(|| ron::from_str::<SaveFile>(raw).map(|file| file.data))
+        })
+        .unwrap_or_default()
+}
+
+/// Writes the save. Failures are logged, the game keeps going without persistence.
+pub fn store(storage: &Storage, data: &SaveData) {
+    // serialize the borrowed data without cloning the whole save
+    storage.store(
+        SAVE_NAME,
+        &SaveFileRef {
+            version: SAVE_VERSION,
+            data,
+        },
+    );
+}
+
+#[derive(Serialize)]
+struct SaveFileRef<'a> {
+    version: u32,
+    data: &'a SaveData,
+}
+
+impl Storage {
+    /// Reads the versioned RON file `name` with `parse`, see `format::parse_versioned`.
+    /// A file that can't be read is set aside, and like a missing one gives `None`.
+    pub fn load<T>(
+        &self,
+        name: &str,
+        expected: u32,
+        parse: impl FnOnce(u32, &str) -> Option<ron::error::SpannedResult<T>>,
+    ) -> Option<T> {
+        let raw = self.read(name)?;
+        match format::parse_versioned(&raw, expected, parse) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                warn!("could not read {name} ({err}), setting it aside");
+                self.set_aside(name, &raw);
+                None
+            }
+        }
+    }
+
+    /// Writes `value` as RON. Failures are logged, the game keeps going with it in memory.
+    pub fn store(&self, name: &str, value: &impl Serialize) {
+        match ron::to_string(value) {
+            Ok(raw) => self.write(name, &raw),
+            Err(err) => error!("could not serialize {name}: {err}"),
+        }
+    }
+}
+
+/// Named blobs of text that outlive the game: files on native, `localStorage` on the web
+#[cfg(not(target_arch = "wasm32"))]
+pub mod storage {
+    use bevy::prelude::*;
+    use std::path::PathBuf;
+
+    /// Where saves, settings and replays are kept. Defaults to the user's data directory,
+    /// insert one before `CorePlugin` to keep them somewhere else.
+    #[derive(Resource, Clone, Debug)]
+    pub struct Storage {
+        dir: PathBuf,
+    }
+
+    impl Default for Storage {
+        fn default() -> Self {
+            Storage::in_dir(data_dir())
+        }
+    }
+
+    impl Storage {
+        pub fn in_dir(dir: impl Into<PathBuf>) -> Self {
+            Storage { dir: dir.into() }
+        }
+
+        pub fn read(&self, name: &str) -> Option<String> {
+            std::fs::read_to_string(self.dir.join(name)).ok()
+        }
+
+        pub fn write(&self, name: &str, raw: &str) {
+            let path = self.dir.join(name);
+            let result =
+                std::fs::create_dir_all(&self.dir).and_then(|()| std::fs::write(&path, raw));
+            if let Err(err) = result {
+                error!("could not write {}: {err}", path.display());
+            }
+        }
+
+        /// Keeps a copy of bad data around so it can be recovered by hand.
+        pub fn set_aside(&self, name: &str, raw: &str) {
+            self.write(&format!("{name}.bak"), raw);
+        }
+    }
+
+    pub fn data_dir() -> PathBuf {
+        let base = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })
+            .unwrap_or_else(|| PathBuf::from("."));
+        base.join("bananas-now")
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub mod storage {
+    use bevy::prelude::*;
+
+    /// Where saves, settings and replays are kept
+    #[derive(Resource, Clone, Debug, Default)]
+    pub struct Storage;
+
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    fn key(name: &str) -> String {
+        format!("bananas-now/{name}")
+    }
+
+    impl Storage {
+        pub fn read(&self, name: &str) -> Option<String> {
+            local_storage()?.get_item(&key(name)).ok()?
+        }
+
+        pub fn write(&self, name: &str, raw: &str) {
+            let written =
+                local_storage().is_some_and(|storage| storage.set_item(&key(name), raw).is_ok());
+            if !written {
+                error!("could not write {name} to localStorage");
+            }
+        }
+
+        /// Keeps a copy of bad data around so it can be recovered by hand.
+        pub fn set_aside(&self, name: &str, raw: &str) {
+            self.write(&format!("{name}.bak"), raw);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(level: usize, mode: GameMode, time_ms: u64, date: u64) -> RunRecord {
+        RunRecord {
+            level,
+            vehicle: 0,
+            mode,
+            time_ms,
+            merch_delivered: 0,
+            money: 0,
+            crashes: 0,
+            date,
+        }
+    }
+
+    /// Storage in a fresh directory of its own, named after `test`
+    fn storage(test: &str) -> (Storage, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("bananas-now-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        (Storage::in_dir(&dir), dir)
+    }
+
+    fn times(board: &[&RunRecord]) -> Vec<u64> {
+        board.iter().map(|run| run.time_ms).collect()
+    }
+
+    #[test]
+    fn the_fastest_run_leads_the_leaderboard() {
+        let save = SaveData {
+            runs: vec![
+                run(0, GameMode::Normal, 30_000, 1),
+                run(0, GameMode::Normal, 10_000, 2),
+                run(0, GameMode::Normal, 20_000, 3),
+            ],
+        };
+        let board = save.leaderboard(0, GameMode::Normal, 0);
+        assert_eq!(times(&board), [10_000, 20_000, 30_000]);
+    }
+
+    #[test]
+    fn the_earlier_run_wins_a_tie() {
+        let save = SaveData {
+            runs: vec![
+                run(0, GameMode::Normal, 10_000, 5),
+                run(0, GameMode::Normal, 10_000, 2),
+            ],
+        };
+        let board = save.leaderboard(0, GameMode::Normal, 0);
+        let dates: Vec<_> = board.iter().map(|run| run.date).collect();
+        assert_eq!(dates, [2, 5]);
+    }
+
+    #[test]
+    fn leaderboards_only_hold_their_level_mode_and_vehicle() {
+        let mut trike = run(0, GameMode::Normal, 4_000, 1);
+        trike.vehicle = 1;
+        let save = SaveData {
+            runs: vec![
+                run(0, GameMode::Normal, 20_000, 1),
+                run(1, GameMode::Normal, 5_000, 1),
+                run(0, GameMode::Hardcore, 6_000, 1),
+                trike,
+            ],
+        };
+        assert_eq!(times(&save.leaderboard(0, GameMode::Normal, 0)), [20_000]);
+        assert_eq!(times(&save.leaderboard(1, GameMode::Normal, 0)), [5_000]);
+        assert_eq!(times(&save.leaderboard(0, GameMode::Hardcore, 0)), [6_000]);
+        assert_eq!(times(&save.leaderboard(0, GameMode::Normal, 1)), [4_000]);
+        assert!(save.leaderboard(1, GameMode::Hardcore, 0).is_empty());
+    }
+
+    #[test]
+    fn times_are_formatted_as_minutes_seconds_and_hundredths() {
+        assert_eq!(format_time(0), "00:00.00");
+        assert_eq!(format_time(1_239), "00:01.23");
+        assert_eq!(format_time(61_500), "01:01.50");
+        assert_eq!(format_time(600_000), "10:00.00");
+    }
+
+    #[test]
+    fn a_missing_save_loads_empty() {
+        let (storage, _) = storage("missing");
+        assert!(load(&storage).runs.is_empty());
+    }
+
+    #[test]
+    fn a_stored_save_loads_again() {
+        let (storage, _) = storage("round-trip");
+        let runs = vec![run(1, GameMode::Hardcore, 12_345, 7)];
+        store(&storage, &SaveData { runs });
+        let loaded = load(&storage);
+        assert_eq!(loaded.runs.len(), 1);
+        assert_eq!(loaded.runs[0].level, 1);
+        assert_eq!(loaded.runs[0].mode, GameMode::Hardcore);
+        assert_eq!(loaded.runs[0].time_ms, 12_345);
+    }
+
+    #[test]
+    fn runs_saved_as_bananas_delivered_still_load() {
+        let (storage, _) = storage("bananas-delivered");
+        storage.write(
+            SAVE_NAME,
+            "(version: 1, data: (runs: [(level: 0, vehicle: 0, mode: Normal, time_ms: 9000, bananas_delivered: 3, money: 30, crashes: 0, date: 1)]))",
+        );
+        let loaded = load(&storage);
+        assert_eq!(loaded.runs.len(), 1);
+        assert_eq!(loaded.runs[0].merch_delivered, 3);
+    }
+
+    #[test]
+    fn a_corrupt_save_loads_empty_and_is_set_aside() {
+        let (storage, dir) = storage("corrupt");
+        storage.write(SAVE_NAME, "not a save at all");
+        assert!(load(&storage).runs.is_empty());
+        let set_aside = std::fs::read_to_string(dir.join(format!("{SAVE_NAME}.bak")));
+        assert_eq!(set_aside.unwrap(), "not a save at all");
+    }
+
+    #[test]
+    fn a_save_in_another_version_is_set_aside() {
+        let (storage, dir) = storage("other-version");
+        let raw = "(version: 99, data: (runs: []))";
+        storage.write(SAVE_NAME, raw);
+        assert!(load(&storage).runs.is_empty());
+        let set_aside = std::fs::read_to_string(dir.join(format!("{SAVE_NAME}.bak")));
+        assert_eq!(set_aside.unwrap(), raw);
+    }
+}
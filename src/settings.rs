@@ -0,0 +1,330 @@
+//! Player settings, stored next to the save, and the screen to change them.
+//!
+//! Settings are RON, wrapped in a `SettingsFile` with a schema version, and
+//! kept with `save::Storage`. Settings that can't be read are set aside and
+//! replaced by the defaults.
+
+use bevy::{
+    input::InputSystem,
+    prelude::*,
+    window::{PrimaryWindow, WindowMode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    controls::{self, ControlsScreen, InputMap},
+    run_if_in_start_level,
+    save::{GameMode, Storage},
+    AppState,
+};
+
+/// Loads the settings, applies the display ones and adds the settings screen,
+/// reachable from the start screen and the pause menu
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        // `CorePlugin` is added first and picks where the settings are kept
+        let settings = load(app.world.resource::<Storage>());
+        app.insert_resource(settings)
+            .init_resource::<SettingsScreen>()
+            .add_systems(
+                PreUpdate,
+                settings_screen_input
+                    .after(InputSystem)
+                    .after(controls::controls_screen_input)
+                    .run_if(run_if_in_start_level.or_else(in_state(AppState::Paused))),
+            )
+            .add_systems(
+                Update,
+                (
+                    show_settings_screen.run_if(
+                        resource_changed::<SettingsScreen>.or_else(resource_changed::<Settings>),
+                    ),
+                    apply_window_mode.run_if(resource_changed::<Settings>),
+                ),
+            );
+    }
+}
+
+const SETTINGS_NAME: &str = "settings.ron";
+
+/// The version settings are written in. Adding a field with a default keeps it.
+pub const SETTINGS_VERSION: u32 = 1;
+
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// What every action is bound to
+    pub controls: InputMap,
+    /// From 0 to 1
+    pub music_volume: f32,
+    /// From 0 to 1
+    pub sfx_volume: f32,
+    pub fullscreen: bool,
+    pub show_fps: bool,
+    /// How much the camera tilts forward with speed and distance, 1 is the original tilt
+    pub camera_tilt: f32,
+    /// The mode a level starts in when it isn't picked explicitly
+    pub difficulty: GameMode,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            controls: InputMap::default(),
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+            fullscreen: false,
+            show_fps: true,
+            camera_tilt: 1.0,
+            difficulty: GameMode::Normal,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SettingsFile {
+    settings: Settings,
+}
+
+#[derive(Serialize)]
+struct SettingsFileRef<'a> {
+    version: u32,
+    settings: &'a Settings,
+}
+
+/// Reads the settings, falling back to the defaults if they are missing or unreadable.
+pub fn load(storage: &Storage) -> Settings {
+    storage
+        .load(SETTINGS_NAME, SETTINGS_VERSION, |version, raw| {
+            (version == SETTINGS_VERSION)
+                .then(|| ron::from_str::<SettingsFile>(raw).map(|file| file.settings))
+        })
+        .unwrap_or_default()
+}
+
+/// Writes the settings. Failures are logged, the game keeps going with them in memory.
+pub fn store(storage: &Storage, settings: &Settings) {
+    storage.store(
+        SETTINGS_NAME,
+        &SettingsFileRef {
+            version: SETTINGS_VERSION,
+            settings,
+        },
+    );
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SettingsItem {
+    MusicVolume,
+    SfxVolume,
+    Fullscreen,
+    ShowFps,
+    CameraTilt,
+    Difficulty,
+    Controls,
+}
+
+impl SettingsItem {
+    const ALL: [SettingsItem; 7] = [
+        SettingsItem::MusicVolume,
+        SettingsItem::SfxVolume,
+        SettingsItem::Fullscreen,
+        SettingsItem::ShowFps,
+        SettingsItem::CameraTilt,
+        SettingsItem::Difficulty,
+        SettingsItem::Controls,
+    ];
+
+    /// The line for this item on the settings screen
+    fn describe(self, settings: &Settings) -> String {
+        let on_off = |on| if on { "On" } else { "Off" };
+        let percent = |value: f32| format!("{:.0}%", value * 100.0);
+        match self {
+            SettingsItem::MusicVolume => {
+                format!("Music volume: {}", percent(settings.music_volume))
+            }
+            SettingsItem::SfxVolume => {
+                format!("Sound effects volume: {}", percent(settings.sfx_volume))
+            }
+            SettingsItem::Fullscreen => format!("Fullscreen: {}", on_off(settings.fullscreen)),
+            SettingsItem::ShowFps => format!("Show FPS: {}", on_off(settings.show_fps)),
+            SettingsItem::CameraTilt => format!("Camera tilt: {}", percent(settings.camera_tilt)),
+            SettingsItem::Difficulty => format!(
+                "Difficulty: {}",
+                match settings.difficulty {
+                    GameMode::Normal => "Normal",
+                    GameMode::Hardcore => "Hardcore",
+                }
+            ),
+            SettingsItem::Controls => "Controls...".to_string(),
+        }
+    }
+
+    /// Changes the setting one step, `up` or down. Toggles ignore the direction.
+    fn change(self, settings: &mut Settings, up: bool) {
+        // in whole steps, so repeated changes don't drift
+        let step = |value: f32, step: f32, max: f32| {
+            let steps = (value / step).round() + if up { 1.0 } else { -1.0 };
+            (steps * step).clamp(0.0, max)
+        };
+        match self {
+            SettingsItem::MusicVolume => {
+                settings.music_volume = step(settings.music_volume, 0.1, 1.0)
+            }
+            SettingsItem::SfxVolume => settings.sfx_volume = step(settings.sfx_volume, 0.1, 1.0),
+            SettingsItem::Fullscreen => settings.fullscreen = !settings.fullscreen,
+            SettingsItem::ShowFps => settings.show_fps = !settings.show_fps,
+            SettingsItem::CameraTilt => {
+                settings.camera_tilt = step(settings.camera_tilt, 0.25, 2.0)
+            }
+            SettingsItem::Difficulty => {
+                settings.difficulty = match settings.difficulty {
+                    GameMode::Normal => GameMode::Hardcore,
+                    GameMode::Hardcore => GameMode::Normal,
+                }
+            }
+            // opened with confirm instead
+            SettingsItem::Controls => {}
+        }
+    }
+}
+
+/// Whether the settings screen is open, and which setting on it is picked
+#[derive(Resource, Default)]
+pub struct SettingsScreen {
+    pub open: bool,
+    selected: usize,
+}
+
+/// Run condition, for menus that must not react while the settings screen is open
+pub fn settings_screen_closed(screen: Res<SettingsScreen>) -> bool {
+    !screen.open
+}
+
+#[derive(Component)]
+struct PartOfSettingsScreen;
+
+/// Opens and closes the settings screen and changes the picked setting.
+///
+/// Like the controls screen, it is worked with keys that no action can be bound to.
+fn settings_screen_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    mut screen: ResMut<SettingsScreen>,
+    mut controls_screen: ResMut<ControlsScreen>,
+    mut settings: ResMut<Settings>,
+    storage: Res<Storage>,
+) {
+    // the controls screen is in front, and it may have just been closed with the same key
+    if controls_screen.open || controls_screen.is_changed() {
+        return;
+    }
+    let pad = |button| {
+        gamepads
+            .iter()
+            .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, button)))
+    };
+
+    if keys.just_pressed(KeyCode::Tab) || pad(GamepadButtonType::Select) {
+        screen.open = !screen.open;
+        screen.selected = 0;
+        return;
+    }
+    if !screen.open {
+        return;
+    }
+    let count = SettingsItem::ALL.len();
+    if keys.just_pressed(KeyCode::ArrowUp) || pad(GamepadButtonType::DPadUp) {
+        screen.selected = (screen.selected + count - 1) % count;
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) || pad(GamepadButtonType::DPadDown) {
+        screen.selected = (screen.selected + 1) % count;
+    }
+
+    let item = SettingsItem::ALL[screen.selected];
+    let confirm = keys.just_pressed(KeyCode::Enter) || pad(GamepadButtonType::South);
+    if confirm && item == SettingsItem::Controls {
+        controls_screen.open = true;
+        return;
+    }
+    let change = if keys.just_pressed(KeyCode::ArrowLeft) || pad(GamepadButtonType::DPadLeft) {
+        Some(false)
+    } else if keys.just_pressed(KeyCode::ArrowRight) || pad(GamepadButtonType::DPadRight) || confirm
+    {
+        Some(true)
+    } else {
+        None
+    };
+    if let Some(up) = change {
+        item.change(&mut settings, up);
+        store(&storage, &settings);
+    }
+}
+
+/// Rebuilds the settings screen whenever it or the settings change.
+fn show_settings_screen(
+    mut commands: Commands,
+    screen: Res<SettingsScreen>,
+    settings: Res<Settings>,
+    old: Query<Entity, With<PartOfSettingsScreen>>,
+) {
+    for entity in &old {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !screen.open {
+        return;
+    }
+
+    let style = TextStyle {
+        font_size: 40.0,
+        color: Color::GOLD,
+        ..default()
+    };
+    let mut lines = vec!["Settings".to_string()];
+    for (index, item) in SettingsItem::ALL.into_iter().enumerate() {
+        let cursor = if index == screen.selected { "> " } else { "  " };
+        lines.push(format!("{cursor}{}", item.describe(&settings)));
+    }
+    lines.push("Up/Down: pick, Left/Right: change, Enter: open, Tab: back".to_string());
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    padding: UiRect::left(Val::Percent(20.0)),
+                    ..default()
+                },
+                background_color: Color::BLACK.into(),
+                // in front of the start screen and the pause menu
+                z_index: ZIndex::Global(5),
+                ..default()
+            },
+            PartOfSettingsScreen,
+        ))
+        .with_children(|parent| {
+            for line in lines {
+                parent.spawn(TextBundle::from_section(line, style.clone()));
+            }
+        });
+}
+
+fn apply_window_mode(
+    settings: Res<Settings>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    for mut window in &mut windows {
+        window.mode = if settings.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        };
+    }
+}
@@ -0,0 +1,278 @@
+//! Sound effects: the engine, and a short sound for each gameplay event.
+//!
+//! The sounds are synthesized at startup instead of loaded from files, and all
+//! of them go through `play_sound_effects`, which applies the sound effects
+//! volume from the settings.
+
+use std::{f32::consts::TAU, sync::Arc, time::Duration};
+
+use bevy::{
+    audio::{AddAudioSource, AudioSourceBundle, Decodable, Source, Volume},
+    ecs::system::SystemParam,
+    prelude::*,
+};
+
+use crate::{
+    car::Car,
+    events::{
+        CustomerLeft, Delivered, HazardHit, HazardKnockedOut, Restocked, RunEnded, ShotFired,
+        WallHit,
+    },
+    settings::Settings,
+    AppState,
+};
+
+const SAMPLE_RATE: u32 = 44_100;
+
+/// The engine is quieter than the other sounds, it plays all the time
+const ENGINE_VOLUME: f32 = 0.3;
+
+/// Plays sound effects for the simulation's gameplay events. Needs `SettingsPlugin`.
+pub struct SfxPlugin;
+
+impl Plugin for SfxPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<Synth>()
+            .add_systems(Startup, setup_sound_effects)
+            .add_systems(Update, (play_sound_effects, engine_sound));
+    }
+}
+
+/// A sound made of precomputed mono samples
+#[derive(Asset, TypePath)]
+struct Synth {
+    samples: Arc<[f32]>,
+}
+
+impl Synth {
+    /// Samples `wave` for `seconds`, with the time in seconds as its argument
+    fn new(seconds: f32, wave: impl Fn(f32) -> f32) -> Self {
+        let count = (seconds * SAMPLE_RATE as f32) as usize;
+        Synth {
+            samples: (0..count)
+                .map(|i| wave(i as f32 / SAMPLE_RATE as f32))
+                .collect(),
+        }
+    }
+}
+
+struct SynthDecoder {
+    samples: Arc<[f32]>,
+    next: usize,
+}
+
+impl Iterator for SynthDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.next).copied();
+        self.next += 1;
+        sample
+    }
+}
+
+impl Source for SynthDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(
+            self.samples.len() as f32 / SAMPLE_RATE as f32,
+        ))
+    }
+}
+
+impl Decodable for Synth {
+    type DecoderItem = f32;
+    type Decoder = SynthDecoder;
+
+    fn decoder(&self) -> SynthDecoder {
+        SynthDecoder {
+            samples: self.samples.clone(),
+            next: 0,
+        }
+    }
+}
+
+/// Handles to the synthesized sounds
+#[derive(Resource)]
+struct SoundEffects {
+    bounce: Handle<Synth>,
+    throw: Handle<Synth>,
+    delivery: Handle<Synth>,
+    restock: Handle<Synth>,
+    missed: Handle<Synth>,
+    crash: Handle<Synth>,
+    fanfare: Handle<Synth>,
+    lose: Handle<Synth>,
+}
+
+/// Marker for the engine loop
+#[derive(Component)]
+struct Engine;
+
+fn sine(frequency: f32, t: f32) -> f32 {
+    (TAU * frequency * t).sin()
+}
+
+/// Repeatable white noise, from a hash of the time
+fn noise(t: f32) -> f32 {
+    let mut x = (t * SAMPLE_RATE as f32) as u32;
+    x = x.wrapping_mul(0x9E37_79B9) ^ (x >> 15);
+    x = x.wrapping_mul(0x85EB_CA6B) ^ (x >> 13);
+    x as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+/// Fades out exponentially, `rate` times per second
+fn decay(rate: f32, t: f32) -> f32 {
+    (-rate * t).exp()
+}
+
+fn setup_sound_effects(mut commands: Commands, mut synths: ResMut<Assets<Synth>>) {
+    let mut add = |seconds, wave: fn(f32) -> f32| synths.add(Synth::new(seconds, wave));
+    let sounds = SoundEffects {
+        bounce: add(0.2, |t| {
+            (sine(90.0, t) * 0.8 + noise(t) * 0.2) * decay(25.0, t)
+        }),
+        throw: add(0.25, |t| {
+            // a whoosh that rises and falls
+            noise(t) * (t / 0.25 * std::f32::consts::PI).sin() * 0.4
+        }),
+        delivery: add(0.5, |t| {
+            let frequency = if t < 0.1 { 1318.5 } else { 1760.0 };
+            sine(frequency, t) * 0.5 * decay(6.0, t % 0.4)
+        }),
+        restock: add(0.4, |t| {
+            // a quick run up, like coins dropping in
+            let step = (t / 0.08).floor().min(3.0);
+            let frequency = 660.0 * 2f32.powf(step * 4.0 / 12.0);
+            sine(frequency, t) * 0.4 * decay(10.0, t % 0.08)
+        }),
+        missed: add(0.5, |t| {
+            // two falling notes, a disappointed "aww"
+            let frequency = if t < 0.2 { 440.0 } else { 349.2 } * (1.0 - t * 0.2);
+            sine(frequency, t) * 0.4 * decay(5.0, t % 0.2)
+        }),
+        crash: add(0.6, |t| {
+            (noise(t) * 0.7 + sine(60.0, t) * 0.3) * decay(7.0, t)
+        }),
+        fanfare: add(1.2, |t| {
+            // C, E, G, then a long high C
+            let (frequency, start) = match t {
+                t if t < 0.15 => (523.3, 0.0),
+                t if t < 0.3 => (659.3, 0.15),
+                t if t < 0.45 => (784.0, 0.3),
+                _ => (1046.5, 0.45),
+            };
+            let t_note = t - start;
+            (sine(frequency, t) + sine(frequency * 2.0, t) * 0.3) * 0.4 * decay(3.0, t_note)
+        }),
+        lose: add(1.2, |t| {
+            // G, F#, F, then a long sagging E
+            let (frequency, start) = match t {
+                t if t < 0.25 => (392.0, 0.0),
+                t if t < 0.5 => (370.0, 0.25),
+                t if t < 0.75 => (349.2, 0.5),
+                _ => (329.6 * (1.0 - (t - 0.75) * 0.1), 0.75),
+            };
+            let t_note = t - start;
+            (sine(frequency, t) + sine(frequency * 0.5, t) * 0.3) * 0.4 * decay(3.0, t_note)
+        }),
+    };
+    commands.insert_resource(sounds);
+
+    // 100 Hz fits the loop a whole number of times, so it repeats without clicks
+    let engine = add(0.2, |t| {
+        (sine(100.0, t) * 0.6 + sine(200.0, t) * 0.3 + sine(300.0, t) * 0.1) * 0.5
+    });
+    commands.spawn((
+        AudioSourceBundle {
+            source: engine,
+            settings: PlaybackSettings::LOOP.paused(),
+        },
+        Engine,
+    ));
+}
+
+/// Every gameplay event that has a sound
+#[derive(SystemParam)]
+struct SoundEvents<'w, 's> {
+    walls: EventReader<'w, 's, WallHit>,
+    hazards: EventReader<'w, 's, HazardHit>,
+    knockouts: EventReader<'w, 's, HazardKnockedOut>,
+    shots: EventReader<'w, 's, ShotFired>,
+    deliveries: EventReader<'w, 's, Delivered>,
+    restocks: EventReader<'w, 's, Restocked>,
+    missed: EventReader<'w, 's, CustomerLeft>,
+    run_ended: EventReader<'w, 's, RunEnded>,
+}
+
+/// Plays the sound of every gameplay event, at the sound effects volume
+fn play_sound_effects(
+    mut commands: Commands,
+    sounds: Option<Res<SoundEffects>>,
+    settings: Res<Settings>,
+    events: SoundEvents,
+) {
+    let Some(sounds) = sounds else {
+        return;
+    };
+    let SoundEvents {
+        mut walls,
+        mut hazards,
+        mut knockouts,
+        mut shots,
+        mut deliveries,
+        mut restocks,
+        mut missed,
+        mut run_ended,
+    } = events;
+    let effects = (walls.read().map(|_| &sounds.bounce))
+        .chain(hazards.read().map(|_| &sounds.crash))
+        .chain(knockouts.read().map(|_| &sounds.bounce))
+        .chain(shots.read().map(|_| &sounds.throw))
+        .chain(deliveries.read().map(|_| &sounds.delivery))
+        .chain(restocks.read().map(|_| &sounds.restock))
+        .chain(missed.read().map(|_| &sounds.missed))
+        .chain(run_ended.read().map(|ended| {
+            if ended.did_win {
+                &sounds.fanfare
+            } else {
+                &sounds.lose
+            }
+        }));
+    for sound in effects {
+        commands.spawn(AudioSourceBundle {
+            source: sound.clone(),
+            settings: PlaybackSettings::DESPAWN.with_volume(Volume::new(settings.sfx_volume)),
+        });
+    }
+}
+
+/// Runs the engine during a run, pitched up with the car's speed
+fn engine_sound(
+    engine: Query<&AudioSink, With<Engine>>,
+    car: Query<&Car>,
+    state: Res<State<AppState>>,
+    settings: Res<Settings>,
+) {
+    let (Ok(sink), Ok(car)) = (engine.get_single(), car.get_single()) else {
+        return;
+    };
+    if *state.get() != AppState::Game {
+        sink.pause();
+        return;
+    }
+    sink.play();
+    sink.set_volume(settings.sfx_volume * ENGINE_VOLUME);
+    sink.set_speed(0.6 + car.vel.length() / car.top_speed);
+}
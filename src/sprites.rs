@@ -0,0 +1,130 @@
+//! The camera, the sprite sheet and the perspective every sprite is drawn in.
+//!
+//! Simulation entities are spawned without sprites; the plugins give them one
+//! as soon as they appear, then place it with `set_transformation` every frame.
+
+use bevy::{ecs::system::SystemParam, prelude::*, utils::hashbrown::HashMap};
+
+use crate::{
+    car::{Car, CarView},
+    settings::Settings,
+    AppState,
+};
+
+pub struct SpritesPlugin;
+
+impl Plugin for SpritesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_sprites);
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct AllSprite {
+    map: HashMap<String, Handle<Image>>,
+}
+
+/// Where the camera is this frame, for systems that draw sprites
+#[derive(SystemParam)]
+pub(crate) struct Camera<'w, 's> {
+    car: Query<'w, 's, &'static Car>,
+    fixed_time: Res<'w, Time<Fixed>>,
+    settings: Res<'w, Settings>,
+    state: Res<'w, State<AppState>>,
+}
+
+impl Camera<'_, '_> {
+    /// How far this frame is from the previous tick to the current one. Outside a run no
+    /// ticks are simulated, so everything stays at the last tick.
+    pub fn alpha(&self) -> f32 {
+        if *self.state.get() == AppState::Game {
+            self.fixed_time.overstep_fraction()
+        } else {
+            1.0
+        }
+    }
+
+    pub fn view(&self) -> CarView {
+        CarView {
+            tilt: self.settings.camera_tilt,
+            ..self.car.single().view(self.alpha())
+        }
+    }
+}
+
+pub(crate) fn get_texture(all_sprites: &AllSprite, key: &str) -> Handle<Image> {
+    all_sprites.map.get(key).unwrap().clone()
+}
+
+pub(crate) fn set_transformation(
+    transform: &mut Transform,
+    pos: &Vec2,
+    scale: f32,
+    car: &CarView,
+    _sprite_size: Vec2,
+) {
+    let theta: f32 = car.tilt
+        * ((car.vel.y.max(0.) / 10.).atan() / 2. + (car.pos.y.max(0.) / 10000.).atan() / 6.);
+    let denom: f32 = (pos.y - car.pos.y) * theta.sin() + 400. * theta.cos();
+    let car_xpos: f32 = 250. * (car.pos.x / 250.).atan();
+    transform.translation = Vec3::new(
+        (pos.x - car.pos.x + car_xpos) / denom * 400.,
+        ((pos.y - car.pos.y) * theta.cos() - 400. * theta.sin()) / denom * (200. / 1.428),
+        400. / denom,
+    );
+    if denom > 0. {
+        transform.scale = 400. / denom * Vec3::new(scale, scale * theta.cos(), scale);
+    } else {
+        transform.scale = Vec3::ZERO;
+    }
+}
+
+/// Gives every newly spawned `T` the sprite `texture`, `set_transformation` places it from then on.
+pub(crate) fn attach_sprite<T: Component>(
+    texture: &'static str,
+    scale: f32,
+) -> impl FnMut(Commands, Query<Entity, Added<T>>, Query<&AllSprite>) {
+    move |mut commands, added, sprites| {
+        for entity in &added {
+            commands.entity(entity).insert(SpriteBundle {
+                texture: get_texture(sprites.single(), texture),
+                transform: Transform::from_scale(Vec3::splat(scale)),
+                ..default()
+            });
+        }
+    }
+}
+
+fn load_sprites(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn(Camera2dBundle::default());
+
+    // Load all sprites
+    let all_assets = vec![
+        "smoke1.png",
+        "smoke2.png",
+        "banana.png",
+        "green-circle.png",
+        "finish.png",
+        "banana-car.png",
+        "banana-speech.png",
+        "coconut.png",
+        "coconut-speech.png",
+        "pineapple.png",
+        "pineapple-speech.png",
+        "depot.png",
+        "depot-used.png",
+        "patience-ring.png",
+        "static-wall.png",
+        "hit-wall.png",
+        "Angry-bougie-cone.png",
+    ];
+    let mut all_sprites = AllSprite {
+        map: Default::default(),
+    };
+    for asset in all_assets {
+        all_sprites
+            .map
+            .insert(asset.to_string(), asset_server.load(asset));
+    }
+    commands.spawn(all_sprites);
+}
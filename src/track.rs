@@ -0,0 +1,503 @@
+//! The track: loading levels, walls, hazard cones and the goal.
+
+use bevy::{
+    asset::{AssetLoadFailedEvent, LoadState},
+    prelude::*,
+};
+
+use crate::{
+    car::{Car, KNOCKBACK_STUN_TICKS},
+    delivery::{Customer, Depot},
+    events::{CustomerLeft, GoalReached, HazardHit, WallHit},
+    level::{ConeBehaviour, Level, LevelLoader, LevelRegistry, Placement},
+    replay::ReplayState,
+    sprites::{attach_sprite, get_texture, set_transformation, AllSprite, Camera},
+    tick_scale, AppState, PartOfLevel, TickSet, HEIGHT_OF_WALL,
+};
+
+/// Levels and everything on them except customers, and with `render` their sprites
+pub struct TrackPlugin {
+    pub render: bool,
+}
+
+impl Plugin for TrackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .init_resource::<Lane>()
+            .add_systems(Startup, setup_track)
+            .add_systems(Update, spawn_loaded_level)
+            .add_systems(
+                FixedUpdate,
+                (
+                    (move_hazards, collision_update_system_hazards)
+                        .chain()
+                        .in_set(TickSet::Collide), // Cheers Dhruba :)
+                    check_in_goal.in_set(TickSet::Goal),
+                ),
+            );
+        if !self.render {
+            return;
+        }
+        app.add_systems(
+            Update,
+            (
+                attach_sprite::<Obstacle>("static-wall.png", 0.1),
+                attach_sprite::<Hazard>("Angry-bougie-cone.png", 1.0),
+                attach_sprite::<Goal>("finish.png", 1.0),
+                obstacle_draw,
+                hazard_draw,
+                goal_draw,
+                (flash_hit_walls, restore_hit_walls).chain(),
+            ),
+        );
+    }
+}
+
+/// The level currently being played, spawned by `spawn_loaded_level` once loaded
+#[derive(Resource)]
+pub struct CurrentLevel {
+    pub index: usize,
+    pub spawned: bool,
+    /// Why the level could not be loaded, in which case it never spawns
+    pub error: Option<String>,
+}
+
+#[derive(Component)]
+pub struct Obstacle {
+    pub pos: Vec2,
+    pub bounce_dir: f32,
+}
+
+#[derive(Component)]
+pub struct Goal {
+    pub pos: Vec2,
+    pub radius: f32,
+}
+
+/// The lane all the way up the track, for customers driving along it
+#[derive(Resource, Default)]
+pub struct Lane {
+    /// One per pair of walls, in track order
+    blocks: Vec<LaneBlock>,
+}
+
+struct LaneBlock {
+    y: f32,
+    center: f32,
+    /// Distance from the center to each wall
+    gap: f32,
+}
+
+impl Lane {
+    /// The blocks either side of `y`, the same one right at a block. None past either end
+    /// of the track.
+    fn around(&self, y: f32) -> Option<(&LaneBlock, &LaneBlock)> {
+        let next = self.blocks.partition_point(|block| block.y < y);
+        let after = self.blocks.get(next)?;
+        match next.checked_sub(1) {
+            Some(before) => Some((&self.blocks[before], after)),
+            None => (after.y == y).then_some((after, after)),
+        }
+    }
+
+    /// The x of the middle of the lane at `y`, between the walls either side of it
+    pub fn center_at(&self, y: f32) -> Option<f32> {
+        let (before, after) = self.around(y)?;
+        if before.y == after.y {
+            return Some(after.center);
+        }
+        Some(before.center + (after.center - before.center) * (y - before.y) / (after.y - before.y))
+    }
+
+    /// Distance from the middle of the lane to the walls at `y`, the narrower of the walls
+    /// either side of it
+    pub fn gap_at(&self, y: f32) -> Option<f32> {
+        let (before, after) = self.around(y)?;
+        Some(before.gap.abs().min(after.gap.abs()))
+    }
+}
+
+/// How long a wall shows that it was hit
+const WALL_HIT_FLASH_SECS: f32 = 0.4;
+
+/// A wall the car just hit, drawn as `hit-wall.png` until the timer runs out
+#[derive(Component)]
+struct WallHitFlash {
+    timer: Timer,
+}
+
+/// Reference ticks a cone stands still after hitting the car, so the car can get away once it
+/// drives again
+const HIT_REST_TICKS: f32 = 2.0 * KNOCKBACK_STUN_TICKS;
+
+#[derive(Component, Clone)]
+pub struct Hazard {
+    pub pos: Vec2,
+    /// Position at the previous tick, to interpolate between ticks when drawing
+    prev_pos: Vec2,
+    /// Where the hazard was placed, patrols are centered on it. It tells hazards apart
+    /// across runs.
+    pub home: Vec2,
+    /// How close the car can get before it hits the hazard
+    pub radius: f32,
+    pub behaviour: ConeBehaviour,
+    /// Whether a thrown banana knocks the hazard out
+    pub knockable: bool,
+    /// Which way a patrol is going, 1 to the right and -1 to the left
+    heading: f32,
+    /// Reference ticks until the hazard moves again after hitting the car
+    rest: f32,
+}
+
+impl Hazard {
+    pub fn new(pos: Vec2, radius: f32, behaviour: ConeBehaviour, knockable: bool) -> Self {
+        Hazard {
+            pos,
+            prev_pos: pos,
+            home: pos,
+            radius,
+            behaviour,
+            knockable,
+            heading: 1.0,
+            rest: 0.0,
+        }
+    }
+
+    pub fn touches(&self, pos: Vec2) -> bool {
+        pos.distance(self.pos) < self.radius
+    }
+
+    /// Knocks `car` back if it ran into the hazard, returns whether it did. A stunned car
+    /// can't be hit, and the hazard rests after a hit, so a chasing cone can't pin the car down.
+    pub fn hit(&mut self, car: &mut Car) -> bool {
+        if car.stun > 0.0 || !self.touches(car.pos) {
+            return false;
+        }
+        car.knock_back(self.pos);
+        self.rest = HIT_REST_TICKS;
+        true
+    }
+
+    /// Moves the hazard by one tick, for a car at `car_pos`
+    pub fn advance(&mut self, car_pos: Vec2, step: f32) {
+        self.prev_pos = self.pos;
+        if self.rest > 0.0 {
+            self.rest = (self.rest - step).max(0.0);
+            return;
+        }
+        match self.behaviour {
+            ConeBehaviour::Still => {}
+            ConeBehaviour::Patrol { range, speed } => {
+                self.pos.x += self.heading * speed * step;
+                // turn around at either end
+                let offset = self.pos.x - self.home.x;
+                if offset.abs() >= range && offset * self.heading > 0.0 {
+                    self.heading = -self.heading;
+                }
+            }
+            ConeBehaviour::Chase { sight, speed } => {
+                let to_car = car_pos - self.pos;
+                if to_car.length() < sight {
+                    self.pos += to_car.normalize_or_zero() * speed * step;
+                }
+            }
+        }
+    }
+}
+
+fn setup_obstacles(commands: &mut Commands, level: &Level) {
+    let mut ypos = -100.0;
+    let mut current_xpos = 0.0;
+    let mut lane = Lane::default();
+
+    for segment in &level.segments {
+        let (num, xpos, more_offset) = (segment.blocks, segment.drift, segment.gap);
+        for placement in &segment.placements {
+            match *placement {
+                Placement::Customer {
+                    xpos: customerx,
+                    wants,
+                    patience,
+                    movement,
+                } => {
+                    commands.spawn((
+                        Customer::new(
+                            Vec2::new(current_xpos + customerx, ypos),
+                            wants,
+                            patience,
+                            movement,
+                        ),
+                        PartOfLevel,
+                    ));
+                }
+                Placement::Depot {
+                    xpos: depot_xpos,
+                    radius,
+                    max_speed,
+                    ref restock,
+                } => {
+                    commands.spawn((
+                        Depot::new(
+                            Vec2::new(current_xpos + depot_xpos, ypos),
+                            radius,
+                            max_speed,
+                            restock.clone(),
+                        ),
+                        PartOfLevel,
+                    ));
+                }
+                Placement::Goal { xpos: goal_xpos } => {
+                    commands.spawn((
+                        Goal {
+                            pos: Vec2::new(current_xpos + goal_xpos, ypos),
+                            radius: 300.,
+                        },
+                        PartOfLevel,
+                    ));
+                }
+                Placement::HangryCone {
+                    xpos: cone_xpos,
+                    radius,
+                    behaviour,
+                    knockable,
+                } => {
+                    commands.spawn((
+                        Hazard::new(
+                            Vec2::new(current_xpos + cone_xpos, ypos),
+                            radius,
+                            behaviour,
+                            knockable,
+                        ),
+                        PartOfLevel,
+                    ));
+                }
+            }
+        }
+
+        for _n in 0..num {
+            commands.spawn((
+                Obstacle {
+                    pos: Vec2::new(current_xpos + xpos - more_offset, ypos),
+                    bounce_dir: more_offset.signum(),
+                },
+                PartOfLevel,
+            ));
+            commands.spawn((
+                Obstacle {
+                    pos: Vec2::new(current_xpos + xpos + more_offset, ypos),
+                    bounce_dir: -more_offset.signum(),
+                },
+                PartOfLevel,
+            ));
+
+            current_xpos += xpos;
+            lane.blocks.push(LaneBlock {
+                y: ypos,
+                center: current_xpos,
+                gap: more_offset,
+            });
+            ypos += HEIGHT_OF_WALL;
+        }
+    }
+    commands.insert_resource(lane);
+}
+
+fn setup_track(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    replay_state: Res<ReplayState>,
+) {
+    setup_level(&mut commands);
+    commands.insert_resource(LevelRegistry::load(&asset_server));
+    commands.insert_resource(CurrentLevel {
+        index: replay_state.starting_level(),
+        spawned: false,
+        error: None,
+    });
+}
+
+/// Spawns the track for `CurrentLevel` as soon as its asset has finished loading, or says why
+/// it couldn't be loaded.
+fn spawn_loaded_level(
+    mut commands: Commands,
+    mut current_level: ResMut<CurrentLevel>,
+    mut registry: ResMut<LevelRegistry>,
+    levels: Res<Assets<Level>>,
+    asset_server: Res<AssetServer>,
+    mut failures: EventReader<AssetLoadFailedEvent<Level>>,
+) {
+    // the load state only says that a level failed, the event says why
+    for failure in failures.read() {
+        registry
+            .errors
+            .insert(failure.id, failure.error.to_string());
+    }
+    if current_level.spawned || current_level.error.is_some() {
+        return;
+    }
+    let Some(handle) = registry.levels.get(current_level.index) else {
+        let error = format!("there is no level {}", current_level.index + 1);
+        error!("{error}");
+        current_level.error = Some(error);
+        return;
+    };
+    if let Some(level) = levels.get(handle) {
+        info!("spawning level \"{}\"", level.name);
+        setup_obstacles(&mut commands, level);
+        current_level.spawned = true;
+    } else if asset_server.get_load_state(handle) == Some(LoadState::Failed) {
+        let error = registry
+            .errors
+            .get(&handle.id())
+            .cloned()
+            .unwrap_or_else(|| "the level failed to load".to_string());
+        error!("{error}");
+        current_level.error = Some(error);
+    }
+}
+
+// The obstacles are spawned separately by `spawn_loaded_level`
+fn setup_level(commands: &mut Commands) {
+    commands.spawn((Car::new(), PartOfLevel));
+}
+
+/// Spawns a fresh car for `level` behind the start screen, its track follows once loaded.
+pub(crate) fn load_level(
+    commands: &mut Commands,
+    current_level: &mut CurrentLevel,
+    next_state: &mut NextState<AppState>,
+    level: usize,
+) {
+    next_state.set(AppState::StartLevel(level));
+    setup_level(commands);
+    current_level.index = level;
+    current_level.spawned = false;
+    current_level.error = None;
+}
+
+fn move_hazards(mut hazards: Query<&mut Hazard>, car: Query<&Car>, fixed_time: Res<Time<Fixed>>) {
+    let car_pos = car.single().pos;
+    let step = tick_scale(&fixed_time);
+    for mut hazard in &mut hazards {
+        hazard.advance(car_pos, step);
+    }
+}
+
+/// Knocks the car back off the hazards it runs into. In hardcore mode that ends the run.
+fn collision_update_system_hazards(
+    mut hazards: Query<(Entity, &mut Hazard)>,
+    mut car: Query<&mut Car>,
+    mut hazard_hits: EventWriter<HazardHit>,
+) {
+    let mut car = car.get_single_mut().unwrap();
+    for (entity, mut hazard) in &mut hazards {
+        if hazard.hit(&mut car) {
+            hazard_hits.send(HazardHit { hazard: entity });
+        }
+    }
+}
+
+fn check_in_goal(
+    car: Query<&mut Car>,
+    goals: Query<&Goal>,
+    customers: Query<&Customer>,
+    mut left: EventReader<CustomerLeft>,
+    mut goal_reached: EventWriter<GoalReached>,
+) {
+    let car = car.iter().next().unwrap();
+    // customers that left this tick are only counted in `missed` at the end of it
+    let customers_left = customers.iter().count() + car.missed + left.read().count();
+    for goal in goals.iter() {
+        if car.pos.y > goal.pos.y && (car.pos.x - goal.pos.x).abs() < goal.radius {
+            goal_reached.send(GoalReached { customers_left });
+            break;
+        }
+    }
+}
+
+/// Shows the walls next to the car as hit whenever it bounces off one.
+fn flash_hit_walls(
+    mut commands: Commands,
+    mut wall_hits: EventReader<WallHit>,
+    mut obstacles: Query<(Entity, &Obstacle, &mut Handle<Image>)>,
+    the_allsprite: Query<&AllSprite>,
+) {
+    for hit in wall_hits.read() {
+        for (entity, obstacle, mut texture) in &mut obstacles {
+            // a bit further than the bounce reaches, the car has moved away since
+            if (obstacle.pos.x - hit.pos.x).abs() < 150.
+                && (obstacle.pos.y - hit.pos.y).abs() < 2. * HEIGHT_OF_WALL
+            {
+                *texture = get_texture(the_allsprite.single(), "hit-wall.png");
+                commands.entity(entity).insert(WallHitFlash {
+                    timer: Timer::from_seconds(WALL_HIT_FLASH_SECS, TimerMode::Once),
+                });
+            }
+        }
+    }
+}
+
+fn restore_hit_walls(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut walls: Query<(Entity, &mut WallHitFlash, &mut Handle<Image>)>,
+    the_allsprite: Query<&AllSprite>,
+) {
+    for (entity, mut flash, mut texture) in &mut walls {
+        if flash.timer.tick(time.delta()).finished() {
+            *texture = get_texture(the_allsprite.single(), "static-wall.png");
+            commands.entity(entity).remove::<WallHitFlash>();
+        }
+    }
+}
+
+fn obstacle_draw(
+    mut obstacle_query: Query<(&Obstacle, &mut Transform)>,
+    camera: Camera,
+    assets: Res<Assets<Image>>,
+    the_allsprite: Query<&AllSprite>,
+) {
+    if let Some(sprite) = assets.get(get_texture(
+        the_allsprite.get_single().unwrap(),
+        "static-wall.png",
+    )) {
+        let view = camera.view();
+        for (obstacle, mut transform) in &mut obstacle_query {
+            set_transformation(&mut transform, &obstacle.pos, 0.1, &view, sprite.size_f32());
+        }
+    }
+}
+fn hazard_draw(
+    mut hazard_query: Query<(&Hazard, &mut Transform)>,
+    camera: Camera,
+    assets: Res<Assets<Image>>,
+    the_allsprite: Query<&AllSprite>,
+) {
+    if let Some(sprite) = assets.get(get_texture(
+        the_allsprite.get_single().unwrap(),
+        "Angry-bougie-cone.png",
+    )) {
+        let view = camera.view();
+        for (hazard, mut transform) in &mut hazard_query {
+            let pos = hazard.prev_pos.lerp(hazard.pos, camera.alpha());
+            set_transformation(&mut transform, &pos, 0.1, &view, sprite.size_f32());
+        }
+    }
+}
+fn goal_draw(
+    mut goal_query: Query<(&Goal, &mut Transform)>,
+    camera: Camera,
+    assets: Res<Assets<Image>>,
+    the_allsprite: Query<&AllSprite>,
+) {
+    if let Some(sprite) = assets.get(get_texture(
+        the_allsprite.get_single().unwrap(),
+        "finish.png",
+    )) {
+        let view = camera.view();
+        for (goal, mut transform) in &mut goal_query {
+            set_transformation(&mut transform, &goal.pos, 1.0, &view, sprite.size_f32());
+        }
+    }
+}
@@ -0,0 +1,413 @@
+//! The HUD: FPS and money counters, the ammo of every merch, the run timer and the car's health.
+
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
+
+use crate::{
+    car::{Car, MAX_HEALTH},
+    delivery::Merch,
+    save,
+    settings::Settings,
+    sprites::{get_texture, AllSprite},
+    AppState, PartOfLevel,
+};
+
+pub struct UiPlugin;
+
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(FrameTimeDiagnosticsPlugin)
+            .add_systems(Startup, setup_counters)
+            .add_systems(
+                Update,
+                (
+                    setup_hud,
+                    (build_ammo_hud, draw_num_ammo).chain(),
+                    fps_text_update_system,
+                    money_text_update_system,
+                    health_text_update_system,
+                ),
+            )
+            .add_systems(Update, text_update_system.run_if(in_state(AppState::Game)))
+            .add_systems(
+                Update,
+                fps_visibility_system.run_if(resource_changed::<Settings>),
+            );
+    }
+}
+
+#[derive(Component)]
+struct TimerText;
+
+/// Marker to find the text entity so we can update it
+#[derive(Component)]
+struct FpsText;
+
+#[derive(Component)]
+struct MoneyText;
+
+#[derive(Component)]
+struct HealthText;
+
+/// How long an ammo count blinks after running out
+const AMMO_FLASH_SECS: f32 = 1.0;
+
+/// Holds a row for every kind of merch the car carries, rebuilt when the kinds change
+#[derive(Component, Default)]
+struct AmmoHud {
+    kinds: Vec<Merch>,
+}
+
+/// A row of the ammo HUD, highlighted while its merch is selected
+#[derive(Component)]
+struct AmmoUi {
+    merch: Merch,
+}
+
+#[derive(Component)]
+struct AmmoUiText {
+    merch: Merch,
+    /// The count last drawn, to notice it running out
+    last: usize,
+    flash: Timer,
+}
+
+fn setup_counters(mut commands: Commands) {
+    setup_fps_counter(&mut commands);
+    setup_money_counter(&mut commands);
+}
+
+fn setup_fps_counter(commands: &mut Commands) {
+    // create our UI root node
+    // this is the wrapper/container for the text
+    // create our text
+    commands.spawn((
+        FpsText,
+        TextBundle {
+            // use two sections, so it is easy to update just the number
+            text: Text::from_sections([
+                TextSection {
+                    value: "FPS: ".into(),
+                    style: TextStyle {
+                        font_size: 16.0,
+                        color: Color::WHITE,
+                        // if you want to use your game's font asset,
+                        // uncomment this and provide the handle:
+                        // font: my_font_handle
+                        ..default()
+                    },
+                },
+                TextSection {
+                    value: " N/A".into(),
+                    style: TextStyle {
+                        font_size: 16.0,
+                        color: Color::WHITE,
+                        // if you want to use your game's font asset,
+                        // uncomment this and provide the handle:
+                        // font: my_font_handle
+                        ..default()
+                    },
+                },
+            ]),
+            ..Default::default()
+        }
+        .with_text_justify(JustifyText::Left)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.0),
+            left: Val::Px(5.0),
+            ..default()
+        }),
+    ));
+}
+
+fn setup_money_counter(commands: &mut Commands) {
+    // create our UI root node
+    // this is the wrapper/container for the text
+    // create our text
+    commands.spawn((
+        MoneyText,
+        TextBundle {
+            // use two sections, so it is easy to update just the number
+            text: Text::from_sections([TextSection {
+                value: " N/A".into(),
+                style: TextStyle {
+                    font_size: 50.0,
+                    color: Color::GOLD,
+                    // if you want to use your game's font asset,
+                    // uncomment this and provide the handle:
+                    // font: my_font_handle
+                    ..default()
+                },
+            }]),
+            ..Default::default()
+        }
+        .with_text_justify(JustifyText::Left)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.0),
+            right: Val::Px(5.0),
+            ..default()
+        }),
+    ));
+}
+
+/// Spawns the ammo HUD, timer and health for a new car.
+fn setup_hud(mut commands: Commands, cars: Query<(), Added<Car>>) {
+    for () in &cars {
+        // filled in by `build_ammo_hud`
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(5.0),
+                    right: Val::Px(5.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::End,
+                    ..default()
+                },
+                ..default()
+            },
+            AmmoHud::default(),
+            PartOfLevel,
+        ));
+
+        commands.spawn((
+            // Create a TextBundle that has a Text with a single section.
+            TextBundle::from_section(
+                "hello\nbevy!",
+                TextStyle {
+                    font_size: 50.0,
+                    color: Color::GOLD,
+                    ..Default::default()
+                },
+            )
+            .with_text_justify(JustifyText::Center)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(5.0),
+                bottom: Val::Px(5.0),
+                ..default()
+            }),
+            TimerText,
+            PartOfLevel,
+        ));
+
+        commands.spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 30.0,
+                    color: Color::GREEN,
+                    ..Default::default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(60.0),
+                left: Val::Px(5.0),
+                ..default()
+            }),
+            HealthText,
+            PartOfLevel,
+        ));
+    }
+}
+
+fn text_update_system(
+    mut query: Query<&mut Text, With<TimerText>>,
+    car: Query<&Car>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let time_ms = car.single().time_ms(&fixed_time);
+    for mut text in &mut query {
+        text.sections[0].value = format!("Time: {}", save::format_time(time_ms));
+    }
+}
+
+/// Gives the ammo HUD an icon and a count for every kind of merch in the car's `ammo`.
+fn build_ammo_hud(
+    mut commands: Commands,
+    mut huds: Query<(Entity, &mut AmmoHud)>,
+    car: Query<&Car>,
+    sprites: Query<&AllSprite>,
+) {
+    let car = car.iter().next().unwrap();
+    let kinds: Vec<_> = car.ammo.keys().copied().collect();
+    for (entity, mut hud) in &mut huds {
+        if hud.kinds == kinds {
+            continue;
+        }
+        // the rows are `PartOfLevel` too, levels are despawned entity by entity
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            for &merch in &kinds {
+                parent
+                    .spawn((
+                        NodeBundle {
+                            style: Style {
+                                align_items: AlignItems::Center,
+                                column_gap: Val::Px(10.0),
+                                padding: UiRect::axes(Val::Px(10.0), Val::Px(2.0)),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        AmmoUi { merch },
+                        PartOfLevel,
+                    ))
+                    .with_children(|row| {
+                        // finished, until the count runs out
+                        let mut flash = Timer::from_seconds(AMMO_FLASH_SECS, TimerMode::Once);
+                        flash.set_elapsed(flash.duration());
+                        row.spawn((
+                            ImageBundle {
+                                image: UiImage::new(get_texture(sprites.single(), merch.sprite())),
+                                style: Style {
+                                    height: Val::Px(50.0),
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            PartOfLevel,
+                        ));
+                        row.spawn((
+                            TextBundle::from_section(
+                                "",
+                                TextStyle {
+                                    font_size: 50.0,
+                                    color: Color::GREEN,
+                                    ..Default::default()
+                                },
+                            ),
+                            AmmoUiText {
+                                merch,
+                                last: car.ammo[&merch],
+                                flash,
+                            },
+                            PartOfLevel,
+                        ));
+                    });
+            }
+        });
+        hud.kinds = kinds.clone();
+    }
+}
+
+/// Updates the counts, highlights the selected merch and blinks counts that just ran out.
+fn draw_num_ammo(
+    mut ammo_ui_text: Query<(&mut AmmoUiText, &mut Text)>,
+    mut rows: Query<(&AmmoUi, &mut BackgroundColor)>,
+    car: Query<&Car>,
+    time: Res<Time>,
+) {
+    let car = car.iter().next().unwrap();
+
+    for (row, mut background) in &mut rows {
+        *background = if row.merch == car.selected {
+            Color::rgba(1.0, 1.0, 1.0, 0.3).into()
+        } else {
+            Color::NONE.into()
+        };
+    }
+
+    for (mut ammo, mut text) in &mut ammo_ui_text {
+        let count = car.ammo.get(&ammo.merch).copied().unwrap_or(0);
+        if count == 0 && ammo.last > 0 {
+            ammo.flash.reset();
+        }
+        ammo.last = count;
+        ammo.flash.tick(time.delta());
+
+        text.sections[0].value = format!("{count}");
+        text.sections[0].style.color = if count > 0 {
+            Color::GREEN
+        } else if !ammo.flash.finished() && ammo.flash.elapsed_secs() % 0.2 < 0.1 {
+            Color::WHITE
+        } else {
+            Color::RED
+        };
+    }
+}
+
+fn money_text_update_system(mut money_text: Query<&mut Text, With<MoneyText>>, car: Query<&Car>) {
+    let car = car.iter().next().unwrap();
+    for mut text in &mut money_text {
+        text.sections[0].value = format!("${}", car.money);
+    }
+}
+
+fn health_text_update_system(
+    mut health_text: Query<&mut Text, With<HealthText>>,
+    car: Query<&Car>,
+) {
+    let car = car.iter().next().unwrap();
+    for mut text in &mut health_text {
+        // hardcore runs end on the first hit, there is no health to show
+        if car.hard_mode {
+            text.sections[0].value.clear();
+            continue;
+        }
+        let health = car.health / MAX_HEALTH;
+        text.sections[0].value = format!("Health: {:.0}%", health * 100.0);
+        text.sections[0].style.color = if health > 0.6 {
+            Color::GREEN
+        } else if health > 0.3 {
+            Color::YELLOW
+        } else {
+            Color::RED
+        };
+    }
+}
+
+fn fps_visibility_system(
+    settings: Res<Settings>,
+    mut query: Query<&mut Visibility, With<FpsText>>,
+) {
+    for mut visibility in &mut query {
+        *visibility = if settings.show_fps {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn fps_text_update_system(
+    diagnostics: Res<DiagnosticsStore>,
+    mut query: Query<&mut Text, With<FpsText>>,
+) {
+    for mut text in &mut query {
+        // try to get a "smoothed" FPS value from Bevy
+        if let Some(value) = diagnostics
+            .get(&FrameTimeDiagnosticsPlugin::FPS)
+            .and_then(|fps| fps.smoothed())
+        {
+            // Format the number as to leave space for 4 digits, just in case,
+            // right-aligned and rounded. This helps readability when the
+            // number changes rapidly.
+            text.sections[1].value = format!("{value:>4.0}");
+
+            // Let's make it extra fancy by changing the color of the
+            // text according to the FPS value:
+            text.sections[1].style.color = if value >= 120.0 {
+                // Above 120 FPS, use green color
+                Color::rgb(0.0, 1.0, 0.0)
+            } else if value >= 60.0 {
+                // Between 60-120 FPS, gradually transition from yellow to green
+                Color::rgb((1.0 - (value - 60.0) / (120.0 - 60.0)) as f32, 1.0, 0.0)
+            } else if value >= 30.0 {
+                // Between 30-60 FPS, gradually transition from red to yellow
+                Color::rgb(1.0, ((value - 30.0) / (60.0 - 30.0)) as f32, 0.0)
+            } else {
+                // Below 30 FPS, use red color
+                Color::rgb(1.0, 0.0, 0.0)
+            }
+        } else {
+            text.sections[1].value = " Failed".into();
+            text.sections[1].style.color = Color::WHITE;
+        }
+    }
+}
@@ -0,0 +1,267 @@
+//! On-disk vehicle format, loaded through the `AssetServer`.
+//!
+//! A vehicle is a RON file (`*.vehicle.ron`) with the car's tuning stats and
+//! the sprites it is drawn with when going straight and steering.
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    ecs::system::SystemParam,
+    prelude::*,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::format::{self, VersionHeader};
+
+/// Vehicles in the order they are picked from. The first one is the default.
+pub const GARAGE: &[&str] = &["vehicles/racecar.vehicle.ron", "vehicles/trike.vehicle.ron"];
+
+/// The only vehicle format version there is. Vehicles in any other are rejected.
+pub const VEHICLE_FORMAT_VERSION: u32 = 1;
+
+/// How a vehicle drives. There are no defaults, every vehicle file sets all of them.
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VehicleStats {
+    pub base_acc: f32,
+    pub top_speed: f32,
+    pub steer_strength: f32,
+    pub drift_strength: f32,
+    pub brake_strength: f32,
+    pub reverse_acc: f32,
+    pub reverse_top_speed: f32,
+    pub handbrake_drift_strength: f32,
+    pub projectile_speed: f32,
+}
+
+/// Image paths for the vehicle going straight and steering
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VehicleSprites {
+    pub center: String,
+    pub left: String,
+    pub right: String,
+    /// Scale the images are drawn at, so vehicles can share a size on screen
+    pub scale: f32,
+}
+
+#[derive(Asset, TypePath, Debug)]
+pub struct Vehicle {
+    pub name: String,
+    pub stats: VehicleStats,
+    pub sprites: VehicleSprites,
+}
+
+/// Handles to every vehicle in `GARAGE`, indexed by vehicle number
+#[derive(Resource)]
+pub struct Garage {
+    pub vehicles: Vec<Handle<Vehicle>>,
+}
+
+impl Garage {
+    pub fn load(asset_server: &AssetServer) -> Self {
+        Garage {
+            vehicles: GARAGE.iter().map(|path| asset_server.load(*path)).collect(),
+        }
+    }
+}
+
+/// The vehicles in `Garage`, for systems that look them up by vehicle number
+#[derive(SystemParam)]
+pub(crate) struct Vehicles<'w> {
+    garage: Res<'w, Garage>,
+    assets: Res<'w, Assets<Vehicle>>,
+}
+
+impl Vehicles<'_> {
+    /// The vehicle, once it has loaded
+    pub fn get(&self, index: usize) -> Option<&Vehicle> {
+        self.assets.get(self.garage.vehicles.get(index)?)
+    }
+
+    pub fn count(&self) -> usize {
+        self.garage.vehicles.len()
+    }
+}
+
+/// The vehicle picked on the start screen, driven in every run until another is picked
+#[derive(Resource, Default)]
+pub struct SelectedVehicle {
+    pub index: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VehicleFile {
+    /// Already checked through `VersionHeader`
+    #[allow(dead_code)]
+    version: u32,
+    name: String,
+    stats: VehicleStats,
+    sprites: VehicleSprites,
+}
+
+#[derive(Debug, Error)]
+pub enum VehicleLoaderError {
+    #[error("could not read vehicle file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("vehicle file is not valid RON: {0}")]
+    Syntax(#[from] ron::error::SpannedError),
+    #[error("field `{field}`: {message}")]
+    Field { field: String, message: String },
+    #[error("unsupported vehicle format version {found} (expected {VEHICLE_FORMAT_VERSION})")]
+    Version { found: u32 },
+}
+
+impl VehicleLoaderError {
+    fn from_path(err: serde_path_to_error::Error<ron::Error>) -> Self {
+        let path: Vec<_> = err.path().iter().collect();
+        VehicleLoaderError::Field {
+            field: format::field_name(&path, err.inner()),
+            message: err.inner().to_string(),
+        }
+    }
+}
+
+/// Parses the contents of a `.vehicle.ron` file.
+pub fn parse_vehicle(bytes: &[u8]) -> Result<Vehicle, VehicleLoaderError> {
+    let mut deserializer = ron::Deserializer::from_bytes(bytes)?;
+    let header: VersionHeader = serde_path_to_error::deserialize(&mut deserializer)
+        .map_err(VehicleLoaderError::from_path)?;
+    if header.version != VEHICLE_FORMAT_VERSION {
+        return Err(VehicleLoaderError::Version {
+            found: header.version,
+        });
+    }
+
+    let mut deserializer = ron::Deserializer::from_bytes(bytes)?;
+    let file: VehicleFile = serde_path_to_error::deserialize(&mut deserializer)
+        .map_err(VehicleLoaderError::from_path)?;
+    deserializer
+        .end()
+        .map_err(|err| VehicleLoaderError::Field {
+            field: ".".into(),
+            message: err.to_string(),
+        })?;
+
+    let stats = file.stats;
+    for (field, value) in [
+        ("stats.base_acc", stats.base_acc),
+        ("stats.top_speed", stats.top_speed),
+        ("stats.steer_strength", stats.steer_strength),
+        ("stats.drift_strength", stats.drift_strength),
+        ("stats.brake_strength", stats.brake_strength),
+        ("stats.reverse_acc", stats.reverse_acc),
+        ("stats.reverse_top_speed", stats.reverse_top_speed),
+        (
+            "stats.handbrake_drift_strength",
+            stats.handbrake_drift_strength,
+        ),
+        ("stats.projectile_speed", stats.projectile_speed),
+        ("sprites.scale", file.sprites.scale),
+    ] {
+        if !(value.is_finite() && value > 0.0) {
+            return Err(VehicleLoaderError::Field {
+                field: field.into(),
+                message: "must be a positive number".into(),
+            });
+        }
+    }
+
+    Ok(Vehicle {
+        name: file.name,
+        stats,
+        sprites: file.sprites,
+    })
+}
+
+#[derive(Default)]
+pub struct VehicleLoader;
+
+impl AssetLoader for VehicleLoader {
+    type Asset = Vehicle;
+    type Settings = ();
+    type Error = VehicleLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Vehicle, VehicleLoaderError>> {
+        Box::pin(async move {
+            let mut bytes = vec![];
+            reader.read_to_end(&mut bytes).await?;
+            parse_vehicle(&bytes)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vehicle.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The racecar, with `stats` swapped in
+    fn vehicle_with(stats: &str) -> String {
+        format!(
+            "(version: 1, name: \"Test\", stats: {stats}, sprites: (center: \"car.png\", left: \"car.png\", right: \"car.png\", scale: 0.1))"
+        )
+    }
+
+    const STATS: &str = "base_acc: 0.7, top_speed: 80.0, steer_strength: 0.0012, drift_strength: 0.06, brake_strength: 1.5, reverse_acc: 0.3, reverse_top_speed: 20.0, handbrake_drift_strength: 0.015";
+
+    fn error(raw: &str) -> VehicleLoaderError {
+        parse_vehicle(raw.as_bytes()).unwrap_err()
+    }
+
+    #[test]
+    fn other_versions_are_rejected_before_their_fields() {
+        let err = error("(version: 2, name: \"Test\", tuning: ())");
+        assert!(
+            matches!(err, VehicleLoaderError::Version { found: 2 }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn an_unknown_field_is_named() {
+        let err = error(&vehicle_with(&format!(
+            "({STATS}, projectile_speed: 100.0, grip: 2.0)"
+        )));
+        assert!(
+            matches!(&err, VehicleLoaderError::Field { field, .. } if field == "stats.grip"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn a_missing_field_is_named() {
+        let err = error(&vehicle_with(&format!("({STATS})")));
+        assert!(
+            matches!(&err, VehicleLoaderError::Field { field, .. } if field == "stats.projectile_speed"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn stats_have_to_be_positive() {
+        let err = error(&vehicle_with(&format!("({STATS}, projectile_speed: -1.0)")));
+        assert!(
+            matches!(&err, VehicleLoaderError::Field { field, .. } if field == "stats.projectile_speed"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn the_vehicles_parse() {
+        for path in GARAGE {
+            let raw = std::fs::read(format!("assets/{path}")).unwrap();
+            parse_vehicle(&raw).unwrap();
+        }
+    }
+}
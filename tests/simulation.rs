@@ -0,0 +1,1581 @@
+//! Plays the game headless with scripted inputs, one simulation tick per update.
+
+use std::time::Duration;
+
+use bananas_now::{
+    car::MAX_HEALTH,
+    delivery::Depot,
+    events::{Delivered, ShotFired},
+    ghost::{best_replay_name, Ghost},
+    level::{ConeBehaviour, CustomerMovement, Level, LevelRegistry, Restock, CAMPAIGN},
+    replay::{Replay, ReplayOutcome, ReplayState, Verification, LAST_REPLAY_NAME},
+    save::{GameMode, SaveData, Storage},
+    settings::Settings,
+    vehicle::{Garage, SelectedVehicle, Vehicle, GARAGE},
+    AppState, Car, CorePlugin, CurrentLevel, Customer, DeliveryPlugin, Goal, Hazard, MenuInput,
+    Merch, Obstacle, PartOfLevel, Projectile, SimulationPlugins, TickInput, DEFAULT_TICK_RATE,
+    HEIGHT_OF_WALL,
+};
+use bevy::{
+    app::PluginGroupBuilder, ecs::event::ManualEventReader, prelude::*, time::TimeUpdateStrategy,
+};
+
+/// An app on the start screen of the first level, with its track and vehicles loaded
+fn headless_app() -> App {
+    app_with(SimulationPlugins.build())
+}
+
+fn app_with(plugins: PluginGroupBuilder) -> App {
+    // keep saves and replays out of the user's data directory, and every test to its own
+    let test = std::thread::current()
+        .name()
+        .unwrap_or("main")
+        .replace("::", "-");
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join(test);
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut app = App::new();
+    app.insert_resource(Storage::in_dir(dir))
+        .add_plugins((MinimalPlugins, plugins))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1.0 / DEFAULT_TICK_RATE,
+        )));
+    for _ in 0..1000 {
+        app.update();
+        let vehicles_loaded = app.world.resource::<Assets<Vehicle>>().len() == GARAGE.len();
+        if app.world.resource::<CurrentLevel>().spawned && vehicles_loaded {
+            return app;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    panic!("the level and vehicles never finished loading");
+}
+
+fn state(app: &App) -> AppState {
+    *app.world.resource::<State<AppState>>().get()
+}
+
+fn car(app: &mut App) -> &Car {
+    app.world.query::<&Car>().single(&app.world)
+}
+
+fn press(app: &mut App, menu_input: MenuInput) {
+    *app.world.resource_mut::<MenuInput>() = menu_input;
+    app.update();
+}
+
+/// Opens the vehicle select screen, moves down to `index` and drives it
+fn pick_vehicle(app: &mut App, index: usize) {
+    // the screen opens on the vehicle driven so far
+    let count = app.world.resource::<Garage>().vehicles.len();
+    let current = app.world.resource::<SelectedVehicle>().index;
+    press(
+        app,
+        MenuInput {
+            vehicles: true,
+            ..default()
+        },
+    );
+    for _ in 0..(index + count - current) % count {
+        press(
+            app,
+            MenuInput {
+                down: true,
+                ..default()
+            },
+        );
+    }
+    press(
+        app,
+        MenuInput {
+            start: true,
+            ..default()
+        },
+    );
+}
+
+fn start(app: &mut App, hardcore: bool) {
+    press(
+        app,
+        MenuInput {
+            start: !hardcore,
+            hardcore,
+            ..default()
+        },
+    );
+    // the state change is applied on the next update
+    app.update();
+    assert_eq!(state(app), AppState::Game);
+}
+
+/// Updates with `input` until the run ends, returning the end state
+fn drive_until_end(app: &mut App, input: TickInput, max_ticks: usize) -> AppState {
+    for _ in 0..max_ticks {
+        *app.world.resource_mut::<TickInput>() = input;
+        app.update();
+        if let AppState::EndLevel { .. } = state(app) {
+            return state(app);
+        }
+    }
+    panic!("the run did not end within {max_ticks} ticks");
+}
+
+fn spawn_goal_ahead(app: &mut App) {
+    let pos = car(app).pos + Vec2::new(0., 300.);
+    app.world.spawn((Goal { pos, radius: 300. }, PartOfLevel));
+}
+
+/// Wins the level being played, with its customers out of the way and a goal right ahead
+fn win(app: &mut App) {
+    despawn_customers(app);
+    spawn_goal_ahead(app);
+    let end = drive_until_end(app, ACCELERATE, 600);
+    assert!(matches!(end, AppState::EndLevel { did_win: true, .. }));
+    app.update();
+}
+
+/// Updates until the track of the level being switched to has spawned
+fn wait_for_level(app: &mut App) {
+    for _ in 0..1000 {
+        app.update();
+        if app.world.resource::<CurrentLevel>().spawned {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    panic!("the level never finished loading");
+}
+
+fn despawn_customers(app: &mut App) {
+    let customers: Vec<_> = app
+        .world
+        .query_filtered::<Entity, With<Customer>>()
+        .iter(&app.world)
+        .collect();
+    for customer in customers {
+        app.world.despawn(customer);
+    }
+}
+
+fn despawn_walls(app: &mut App) {
+    let walls: Vec<_> = app
+        .world
+        .query_filtered::<Entity, With<Obstacle>>()
+        .iter(&app.world)
+        .collect();
+    for wall in walls {
+        app.world.despawn(wall);
+    }
+}
+
+const ACCELERATE: TickInput = TickInput {
+    throttle: 1.0,
+    steer: 0.0,
+    brake: 0.0,
+    handbrake: false,
+    shoot_left: false,
+    shoot_right: false,
+    cycle_merch: false,
+};
+
+const STEER_LEFT: TickInput = TickInput {
+    throttle: 1.0,
+    steer: 1.0,
+    brake: 0.0,
+    handbrake: false,
+    shoot_left: false,
+    shoot_right: false,
+    cycle_merch: false,
+};
+
+#[test]
+fn accelerating_moves_the_car_up_the_track() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    let ticks_before = car(&mut app).ticks_elapsed;
+    for _ in 0..60 {
+        *app.world.resource_mut::<TickInput>() = ACCELERATE;
+        app.update();
+    }
+    let car = car(&mut app);
+    assert_eq!(car.ticks_elapsed, ticks_before + 60);
+    assert!(car.pos.y > 0.);
+    assert_eq!(car.pos.x, 100.);
+}
+
+#[test]
+fn braking_stops_the_car_then_reverses() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    for _ in 0..60 {
+        *app.world.resource_mut::<TickInput>() = ACCELERATE;
+        app.update();
+    }
+    let brake = TickInput {
+        brake: 1.0,
+        ..default()
+    };
+    for _ in 0..600 {
+        *app.world.resource_mut::<TickInput>() = brake;
+        app.update();
+        if car(&mut app).reversing {
+            break;
+        }
+    }
+    let furthest = car(&mut app).pos.y;
+    for _ in 0..60 {
+        *app.world.resource_mut::<TickInput>() = brake;
+        app.update();
+    }
+    let car = car(&mut app);
+    assert!(car.reversing);
+    assert!(car.pos.y < furthest);
+    assert!(car.vel.length() <= car.reverse_top_speed);
+}
+
+#[test]
+fn a_run_drives_the_first_vehicle_until_another_is_picked() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    let racecar = app
+        .world
+        .resource::<Assets<Vehicle>>()
+        .get(&app.world.resource::<Garage>().vehicles[0])
+        .unwrap()
+        .stats;
+    let car = car(&mut app);
+    assert_eq!(car.vehicle, 0);
+    assert_eq!(car.top_speed, racecar.top_speed);
+    assert_eq!(car.steer_strength, racecar.steer_strength);
+    assert_eq!(car.projectile_speed, racecar.projectile_speed);
+}
+
+#[test]
+fn the_picked_vehicle_tunes_the_car_and_has_its_own_leaderboard() {
+    let mut app = headless_app();
+    let board_sizes = |app: &mut App| {
+        let save = app.world.query::<&SaveData>().single(&app.world);
+        (
+            save.leaderboard(0, GameMode::Normal, 0).len(),
+            save.leaderboard(0, GameMode::Normal, 1).len(),
+        )
+    };
+    let (racecar_runs, trike_runs) = board_sizes(&mut app);
+    pick_vehicle(&mut app, 1);
+    start(&mut app, false);
+    let trike = app
+        .world
+        .resource::<Assets<Vehicle>>()
+        .get(&app.world.resource::<Garage>().vehicles[1])
+        .unwrap()
+        .stats;
+    let car = car(&mut app);
+    assert_eq!(car.vehicle, 1);
+    assert_eq!(car.top_speed, trike.top_speed);
+    assert_eq!(car.projectile_speed, trike.projectile_speed);
+
+    despawn_customers(&mut app);
+    spawn_goal_ahead(&mut app);
+    drive_until_end(&mut app, ACCELERATE, 600);
+    assert_eq!(board_sizes(&mut app), (racecar_runs, trike_runs + 1));
+}
+
+#[test]
+fn the_vehicle_select_screen_holds_the_start_screen_until_it_is_closed() {
+    let mut app = headless_app();
+    let selected = |app: &App| app.world.resource::<SelectedVehicle>().index;
+    press(
+        &mut app,
+        MenuInput {
+            vehicles: true,
+            ..default()
+        },
+    );
+    // confirming picks the vehicle instead of starting a run
+    press(
+        &mut app,
+        MenuInput {
+            up: true,
+            ..default()
+        },
+    );
+    press(
+        &mut app,
+        MenuInput {
+            start: true,
+            ..default()
+        },
+    );
+    app.update();
+    assert_eq!(state(&app), AppState::StartLevel(0));
+    let count = app.world.resource::<Garage>().vehicles.len();
+    assert_eq!(selected(&app), count - 1);
+
+    // up and down do nothing on the start screen itself
+    pick_vehicle(&mut app, 0);
+    press(
+        &mut app,
+        MenuInput {
+            down: true,
+            ..default()
+        },
+    );
+    assert_eq!(selected(&app), 0);
+
+    // closing it without confirming keeps the vehicle that was picked before
+    for menu_input in [
+        MenuInput {
+            vehicles: true,
+            ..default()
+        },
+        MenuInput {
+            down: true,
+            ..default()
+        },
+        MenuInput {
+            vehicles: true,
+            ..default()
+        },
+    ] {
+        press(&mut app, menu_input);
+    }
+    assert_eq!(selected(&app), 0);
+    start(&mut app, false);
+    assert_eq!(car(&mut app).vehicle, 0);
+}
+
+#[test]
+fn pausing_freezes_the_run_until_it_is_resumed() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    for _ in 0..30 {
+        *app.world.resource_mut::<TickInput>() = ACCELERATE;
+        app.update();
+    }
+    press(
+        &mut app,
+        MenuInput {
+            pause: true,
+            ..default()
+        },
+    );
+    app.update();
+    assert_eq!(state(&app), AppState::Paused);
+    let (ticks, pos) = (car(&mut app).ticks_elapsed, car(&mut app).pos);
+    for _ in 0..30 {
+        *app.world.resource_mut::<TickInput>() = ACCELERATE;
+        app.update();
+    }
+    assert_eq!(car(&mut app).ticks_elapsed, ticks);
+    assert_eq!(car(&mut app).pos, pos);
+
+    press(
+        &mut app,
+        MenuInput {
+            pause: true,
+            ..default()
+        },
+    );
+    app.update();
+    assert_eq!(state(&app), AppState::Game);
+    *app.world.resource_mut::<TickInput>() = ACCELERATE;
+    app.update();
+    // the same run goes on, it is not started over
+    assert!(car(&mut app).ticks_elapsed > ticks);
+    let recording = app.world.resource::<ReplayState>().recording.as_ref();
+    assert!(recording.unwrap().ticks().count() > ticks);
+}
+
+#[test]
+fn restarting_from_the_pause_menu_starts_the_level_again() {
+    let mut app = headless_app();
+    start(&mut app, true);
+    for _ in 0..30 {
+        *app.world.resource_mut::<TickInput>() = ACCELERATE;
+        app.update();
+    }
+    press(
+        &mut app,
+        MenuInput {
+            pause: true,
+            ..default()
+        },
+    );
+    app.update();
+    press(
+        &mut app,
+        MenuInput {
+            down: true,
+            ..default()
+        },
+    );
+    press(
+        &mut app,
+        MenuInput {
+            start: true,
+            ..default()
+        },
+    );
+    // the restart waits for the track to respawn, then starts by itself
+    for _ in 0..100 {
+        app.update();
+        if state(&app) == AppState::Game {
+            break;
+        }
+    }
+    assert_eq!(state(&app), AppState::Game);
+    let car = car(&mut app);
+    assert!(car.hard_mode);
+    assert!(car.ticks_elapsed < 30);
+}
+
+#[test]
+fn walls_only_bounce_the_car_in_normal_mode() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    for _ in 0..600 {
+        *app.world.resource_mut::<TickInput>() = STEER_LEFT;
+        app.update();
+        if car(&mut app).crashes > 0 {
+            break;
+        }
+    }
+    assert!(car(&mut app).crashes > 0);
+    app.update();
+    assert_eq!(state(&app), AppState::Game);
+}
+
+#[test]
+fn the_hardcore_difficulty_setting_starts_levels_in_hardcore_mode() {
+    let mut app = headless_app();
+    app.insert_resource(Settings {
+        difficulty: GameMode::Hardcore,
+        ..default()
+    });
+    start(&mut app, false);
+    assert!(car(&mut app).hard_mode);
+}
+
+#[test]
+fn wall_hits_damage_the_car() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    for _ in 0..600 {
+        *app.world.resource_mut::<TickInput>() = STEER_LEFT;
+        app.update();
+        if car(&mut app).crashes > 0 {
+            break;
+        }
+    }
+    assert!(car(&mut app).health < MAX_HEALTH);
+    assert_eq!(state(&app), AppState::Game);
+}
+
+#[test]
+fn a_wrecked_car_ends_the_run() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    app.world
+        .query::<&mut Car>()
+        .single_mut(&mut app.world)
+        .health = 1.0;
+    let end = drive_until_end(&mut app, STEER_LEFT, 600);
+    assert!(matches!(
+        end,
+        AppState::EndLevel {
+            did_win: false,
+            did_finish: false,
+            ..
+        }
+    ));
+    assert_eq!(car(&mut app).health, 0.0);
+}
+
+fn spawn_cone_ahead(app: &mut App) -> Vec2 {
+    let pos = car(app).pos + Vec2::new(0., 400.);
+    app.world.spawn((
+        Hazard::new(pos, 150., ConeBehaviour::Still, false),
+        PartOfLevel,
+    ));
+    pos
+}
+
+#[test]
+fn cones_knock_the_car_back_in_normal_mode() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    let cone = spawn_cone_ahead(&mut app);
+    for _ in 0..300 {
+        *app.world.resource_mut::<TickInput>() = ACCELERATE;
+        app.update();
+        if car(&mut app).stun > 0.0 {
+            break;
+        }
+    }
+    assert!(car(&mut app).stun > 0.0);
+    assert!(car(&mut app).vel.y < 0.0);
+    // the hit counts as a crash, like a wall
+    assert_eq!(car(&mut app).crashes, 1);
+    // the car rolls clear of the cone before it can drive again
+    while car(&mut app).stun > 0.0 {
+        app.update();
+    }
+    assert!(car(&mut app).pos.distance(cone) >= 150.);
+    assert_eq!(state(&app), AppState::Game);
+}
+
+#[test]
+fn patrolling_cones_turn_around_at_the_end_of_their_range() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    let home = car(&mut app).pos + Vec2::new(0., 3000.);
+    let behaviour = ConeBehaviour::Patrol {
+        range: 100.,
+        speed: 5.,
+    };
+    let cone = app
+        .world
+        .spawn((Hazard::new(home, 150., behaviour, false), PartOfLevel))
+        .id();
+    let mut furthest = 0.0f32;
+    for _ in 0..120 {
+        app.update();
+        let pos = app.world.get::<Hazard>(cone).unwrap().pos;
+        assert_eq!(pos.y, home.y);
+        furthest = furthest.max((pos.x - home.x).abs());
+    }
+    assert!((100. ..=105.).contains(&furthest));
+}
+
+#[test]
+fn chasing_cones_close_in_on_the_car() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    let start = car(&mut app).pos + Vec2::new(600., 600.);
+    let behaviour = ConeBehaviour::Chase {
+        sight: 1000.,
+        speed: 3.,
+    };
+    let cone = app
+        .world
+        .spawn((Hazard::new(start, 150., behaviour, false), PartOfLevel))
+        .id();
+    for _ in 0..30 {
+        app.update();
+    }
+    let pos = app.world.get::<Hazard>(cone).unwrap().pos;
+    let car_pos = car(&mut app).pos;
+    assert!(pos.distance(car_pos) < start.distance(car_pos));
+}
+
+#[test]
+fn the_car_drives_free_of_a_chasing_cone_once_the_stun_ends() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    let behaviour = ConeBehaviour::Chase {
+        sight: 800.,
+        speed: 3.,
+    };
+    let pos = car(&mut app).pos - Vec2::new(0., 300.);
+    let cone = app
+        .world
+        .spawn((Hazard::new(pos, 150., behaviour, false), PartOfLevel))
+        .id();
+    // wait for the cone to catch up with the parked car
+    for _ in 0..300 {
+        app.update();
+        if car(&mut app).stun > 0.0 {
+            break;
+        }
+    }
+    assert!(car(&mut app).stun > 0.0);
+    while car(&mut app).stun > 0.0 {
+        app.update();
+    }
+    for _ in 0..120 {
+        *app.world.resource_mut::<TickInput>() = ACCELERATE;
+        app.update();
+        assert_eq!(car(&mut app).stun, 0.0);
+    }
+    let cone = app.world.get::<Hazard>(cone).unwrap().pos;
+    assert!(car(&mut app).pos.distance(cone) > 800.);
+}
+
+#[test]
+fn bananas_knock_out_knockable_cones() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    despawn_customers(&mut app);
+    let pos = car(&mut app).pos - Vec2::new(300., 0.);
+    let cone = app
+        .world
+        .spawn((
+            Hazard::new(pos, 150., ConeBehaviour::Still, true),
+            PartOfLevel,
+        ))
+        .id();
+    *app.world.resource_mut::<TickInput>() = TickInput {
+        shoot_left: true,
+        ..default()
+    };
+    for _ in 0..10 {
+        app.update();
+    }
+    assert!(app.world.get_entity(cone).is_none());
+}
+
+#[test]
+fn the_ghost_drives_past_cones_its_run_knocked_out() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    despawn_customers(&mut app);
+    // close enough to catch the car, unless a banana knocks it out first
+    let pos = car(&mut app).pos - Vec2::new(200., 0.);
+    let behaviour = ConeBehaviour::Chase {
+        sight: 800.,
+        speed: 3.,
+    };
+    app.world
+        .spawn((Hazard::new(pos, 150., behaviour, true), PartOfLevel));
+    let hazards: Vec<Hazard> = app
+        .world
+        .query::<&Hazard>()
+        .iter(&app.world)
+        .cloned()
+        .collect();
+
+    *app.world.resource_mut::<TickInput>() = TickInput {
+        shoot_left: true,
+        ..default()
+    };
+    app.update();
+    for _ in 0..120 {
+        *app.world.resource_mut::<TickInput>() = ACCELERATE;
+        app.update();
+    }
+    let car = car(&mut app);
+    let (car_pos, ticks) = (car.pos, car.ticks_elapsed);
+    assert_eq!(car.stun, 0.0);
+
+    let mut replay = app
+        .world
+        .resource::<ReplayState>()
+        .recording
+        .clone()
+        .unwrap();
+    assert_eq!(replay.knockouts.len(), 1);
+    let mut obstacles = app.world.query::<&Obstacle>();
+    let obstacles: Vec<&Obstacle> = obstacles.iter(&app.world).collect();
+    let vehicle = app
+        .world
+        .resource::<Assets<Vehicle>>()
+        .get(&app.world.resource::<Garage>().vehicles[0])
+        .unwrap();
+    let ghost = Ghost::simulate(&replay, vehicle, obstacles.clone(), &hazards, 1.0);
+    assert_eq!(ghost.at(ticks, 1.0).0, car_pos);
+
+    // without the knockout the cone would have caught the ghost
+    replay.knockouts.clear();
+    let ghost = Ghost::simulate(&replay, vehicle, obstacles, &hazards, 1.0);
+    assert_ne!(ghost.at(ticks, 1.0).0, car_pos);
+}
+
+#[test]
+fn a_best_run_on_another_version_of_the_level_is_replaced_by_a_slower_one() {
+    let mut app = headless_app();
+    let layout = app
+        .world
+        .resource::<Assets<Level>>()
+        .get(&app.world.resource::<LevelRegistry>().levels[0])
+        .unwrap()
+        .layout;
+    let name = best_replay_name(0, false);
+    let mut stale = Replay::new(0, layout.wrapping_add(1), 0, false, DEFAULT_TICK_RATE);
+    // faster than any run could be
+    stale.outcome = Some(ReplayOutcome {
+        did_win: true,
+        did_finish: true,
+        ticks: 1,
+    });
+    app.world.resource::<Storage>().store(&name, &stale);
+
+    start(&mut app, false);
+    assert_eq!(
+        app.world
+            .resource::<ReplayState>()
+            .recording
+            .as_ref()
+            .unwrap()
+            .layout,
+        layout
+    );
+    win(&mut app);
+    let raw = app.world.resource::<Storage>().read(&name).unwrap();
+    let best = Replay::parse(&raw).unwrap();
+    assert_eq!(best.layout, layout);
+    assert!(best.outcome.unwrap().ticks > 1);
+}
+
+#[test]
+fn cones_end_the_run_in_hardcore_mode() {
+    let mut app = headless_app();
+    start(&mut app, true);
+    spawn_cone_ahead(&mut app);
+    let end = drive_until_end(&mut app, ACCELERATE, 300);
+    assert!(matches!(
+        end,
+        AppState::EndLevel {
+            did_win: false,
+            did_finish: false,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn walls_end_the_run_in_hardcore_mode() {
+    let mut app = headless_app();
+    start(&mut app, true);
+    let end = drive_until_end(&mut app, STEER_LEFT, 600);
+    assert!(matches!(
+        end,
+        AppState::EndLevel {
+            level: 0,
+            did_win: false,
+            did_finish: false,
+            ..
+        }
+    ));
+    assert_eq!(car(&mut app).crashes, 1);
+}
+
+#[test]
+fn bananas_thrown_at_customers_are_delivered() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    let pos = car(&mut app).pos - Vec2::new(200., 0.);
+    let customer = app
+        .world
+        .spawn((
+            Customer::new(pos, Merch::Banana, None, CustomerMovement::Parked),
+            PartOfLevel,
+        ))
+        .id();
+    *app.world.resource_mut::<TickInput>() = TickInput {
+        shoot_left: true,
+        ..default()
+    };
+    for _ in 0..10 {
+        app.update();
+    }
+    let car = car(&mut app);
+    assert_eq!(car.delivered, 1);
+    assert_eq!(car.money, 1);
+    assert_eq!(car.ammo[&Merch::Banana], 9);
+    assert!(app.world.get_entity(customer).is_none());
+}
+
+#[test]
+fn throwing_and_delivering_send_events() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    let pos = car(&mut app).pos - Vec2::new(200., 0.);
+    app.world.spawn((
+        Customer::new(pos, Merch::Banana, None, CustomerMovement::Parked),
+        PartOfLevel,
+    ));
+    *app.world.resource_mut::<TickInput>() = TickInput {
+        shoot_left: true,
+        ..default()
+    };
+    let mut shots = ManualEventReader::<ShotFired>::default();
+    let mut deliveries = ManualEventReader::<Delivered>::default();
+    let (mut shot, mut delivered) = (vec![], vec![]);
+    for _ in 0..10 {
+        app.update();
+        shot.extend(shots.read(app.world.resource()).map(|shot| shot.merch));
+        delivered.extend(deliveries.read(app.world.resource()).map(|d| d.merch));
+    }
+    assert_eq!(shot, [Merch::Banana]);
+    assert_eq!(delivered, [Merch::Banana]);
+    assert_eq!(car(&mut app).delivered, 1);
+}
+
+#[test]
+fn nothing_is_thrown_without_the_delivery_plugin() {
+    let mut app = app_with(SimulationPlugins.build().disable::<DeliveryPlugin>());
+    start(&mut app, false);
+    *app.world.resource_mut::<TickInput>() = TickInput {
+        shoot_left: true,
+        ..default()
+    };
+    for _ in 0..10 {
+        app.update();
+    }
+    assert_eq!(car(&mut app).ammo[&Merch::Banana], 10);
+    assert_eq!(app.world.query::<&Projectile>().iter(&app.world).count(), 0);
+}
+
+#[test]
+fn customers_only_take_the_merch_they_want() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    app.world
+        .query::<&mut Car>()
+        .single_mut(&mut app.world)
+        .ammo = [(Merch::Banana, 10), (Merch::Coconut, 3)].into();
+    let pos = car(&mut app).pos - Vec2::new(200., 0.);
+    let customer = app
+        .world
+        .spawn((
+            Customer::new(pos, Merch::Coconut, None, CustomerMovement::Parked),
+            PartOfLevel,
+        ))
+        .id();
+    let shoot = TickInput {
+        shoot_left: true,
+        ..default()
+    };
+    *app.world.resource_mut::<TickInput>() = shoot;
+    for _ in 0..10 {
+        app.update();
+    }
+    assert!(app.world.get_entity(customer).is_some());
+
+    *app.world.resource_mut::<TickInput>() = TickInput {
+        cycle_merch: true,
+        ..shoot
+    };
+    for _ in 0..10 {
+        app.update();
+    }
+    let car = car(&mut app);
+    assert_eq!(car.selected, Merch::Coconut);
+    assert_eq!(car.ammo[&Merch::Coconut], 2);
+    assert_eq!(car.delivered, 1);
+    assert!(app.world.get_entity(customer).is_none());
+}
+
+#[test]
+fn cycling_merch_wraps_around_the_carried_kinds() {
+    let mut car = Car::new();
+    car.load_ammo([(Merch::Pineapple, 1), (Merch::Banana, 1)].into());
+    assert_eq!(car.selected, Merch::Banana);
+    car.cycle_merch();
+    assert_eq!(car.selected, Merch::Pineapple);
+    car.cycle_merch();
+    assert_eq!(car.selected, Merch::Banana);
+}
+
+#[test]
+fn lobbed_merch_lands_and_is_gone() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    // nothing for it to hit on the way
+    despawn_customers(&mut app);
+    despawn_walls(&mut app);
+    app.world
+        .query::<&mut Car>()
+        .single_mut(&mut app.world)
+        .load_ammo([(Merch::Pineapple, 1)].into());
+    *app.world.resource_mut::<TickInput>() = TickInput {
+        shoot_left: true,
+        ..default()
+    };
+    app.update();
+    let mut projectiles = app.world.query::<&Projectile>();
+    let mut ticks_in_the_air = 0;
+    while let Some(projectile) = projectiles.iter(&app.world).next() {
+        assert!(projectile.height >= 0.0);
+        ticks_in_the_air += 1;
+        assert!(ticks_in_the_air < 100, "the pineapple never landed");
+        app.update();
+    }
+    // thrown up at 12 and pulled down by 1 every tick
+    assert!((20..30).contains(&ticks_in_the_air));
+}
+
+fn spawn_depot_at_car(app: &mut App, max_speed: Option<f32>, restock: Restock) -> Entity {
+    let pos = car(app).pos;
+    app.world
+        .spawn((Depot::new(pos, 300., max_speed, restock), PartOfLevel))
+        .id()
+}
+
+#[test]
+fn depots_restock_the_car_once() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    let mut car_mut = app.world.query::<&mut Car>();
+    car_mut.single_mut(&mut app.world).ammo = [(Merch::Banana, 0)].into();
+    let depot = spawn_depot_at_car(&mut app, None, Restock::Full);
+    app.update();
+    assert_eq!(car(&mut app).ammo[&Merch::Banana], 10);
+    assert!(app.world.get::<Depot>(depot).unwrap().used);
+
+    car_mut.single_mut(&mut app.world).ammo = [(Merch::Banana, 0)].into();
+    app.update();
+    assert_eq!(car(&mut app).ammo[&Merch::Banana], 0);
+}
+
+#[test]
+fn depots_can_add_merch_the_car_did_not_carry() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    spawn_depot_at_car(
+        &mut app,
+        None,
+        Restock::Add([(Merch::Banana, 2), (Merch::Coconut, 3)].into()),
+    );
+    app.update();
+    let car = car(&mut app);
+    assert_eq!(car.ammo[&Merch::Banana], 12);
+    assert_eq!(car.ammo[&Merch::Coconut], 3);
+}
+
+#[test]
+fn depots_with_a_speed_limit_ignore_fast_cars() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    let mut car_mut = app.world.query::<&mut Car>();
+    let mut fast_car = car_mut.single_mut(&mut app.world);
+    fast_car.ammo = [(Merch::Banana, 0)].into();
+    fast_car.vel = Vec2::new(0., 30.);
+    let depot = spawn_depot_at_car(&mut app, Some(10.), Restock::Full);
+    for _ in 0..10 {
+        *app.world.resource_mut::<TickInput>() = ACCELERATE;
+        app.update();
+    }
+    assert!(car(&mut app).vel.length() > 10.);
+    assert_eq!(car(&mut app).ammo[&Merch::Banana], 0);
+    assert!(!app.world.get::<Depot>(depot).unwrap().used);
+}
+
+#[test]
+fn customers_only_lose_patience_once_the_car_comes_near() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    let pos = car(&mut app).pos + Vec2::new(0., 5000.);
+    let customer = app
+        .world
+        .spawn((
+            Customer::new(pos, Merch::Banana, Some(0.5), CustomerMovement::Parked),
+            PartOfLevel,
+        ))
+        .id();
+    for _ in 0..60 {
+        app.update();
+    }
+    let customer = app.world.get::<Customer>(customer).unwrap();
+    assert_eq!(customer.waited, None);
+    assert_eq!(customer.patience_left(), 1.0);
+}
+
+#[test]
+fn impatient_customers_leave_and_lose_the_run() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    despawn_customers(&mut app);
+    let pos = car(&mut app).pos - Vec2::new(500., 0.);
+    let customer = app
+        .world
+        .spawn((
+            Customer::new(pos, Merch::Banana, Some(0.5), CustomerMovement::Parked),
+            PartOfLevel,
+        ))
+        .id();
+    for _ in 0..(DEFAULT_TICK_RATE as usize / 2 + 2) {
+        app.update();
+    }
+    assert!(app.world.get_entity(customer).is_none());
+    assert_eq!(car(&mut app).missed, 1);
+
+    spawn_goal_ahead(&mut app);
+    let end = drive_until_end(&mut app, ACCELERATE, 600);
+    assert!(matches!(
+        end,
+        AppState::EndLevel {
+            did_win: false,
+            did_finish: true,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn a_customer_hit_as_their_patience_runs_out_is_only_delivered() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    despawn_customers(&mut app);
+    let pos = car(&mut app).pos - Vec2::new(150., 0.);
+    app.world.spawn((
+        Customer::new(pos, Merch::Banana, Some(0.0), CustomerMovement::Parked),
+        PartOfLevel,
+    ));
+    *app.world.resource_mut::<TickInput>() = TickInput {
+        shoot_left: true,
+        ..default()
+    };
+    app.update();
+    let car = car(&mut app);
+    assert_eq!((car.delivered, car.missed), (1, 0));
+}
+
+#[test]
+fn one_banana_only_reaches_one_customer() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    despawn_customers(&mut app);
+    let hazards_before = app.world.query::<&Hazard>().iter(&app.world).count();
+    let pos = car(&mut app).pos - Vec2::new(150., 0.);
+    for offset in [Vec2::ZERO, Vec2::new(0., 50.)] {
+        app.world.spawn((
+            Customer::new(pos + offset, Merch::Banana, None, CustomerMovement::Parked),
+            PartOfLevel,
+        ));
+    }
+    app.world.spawn((
+        Hazard::new(pos, 150., ConeBehaviour::Still, true),
+        PartOfLevel,
+    ));
+    *app.world.resource_mut::<TickInput>() = TickInput {
+        shoot_left: true,
+        ..default()
+    };
+    for _ in 0..10 {
+        app.update();
+    }
+    assert_eq!(car(&mut app).delivered, 1);
+    let customers_left = app.world.query::<&Customer>().iter(&app.world).count();
+    let hazards_left = app.world.query::<&Hazard>().iter(&app.world).count();
+    assert_eq!(customers_left, 1);
+    assert_eq!(hazards_left, hazards_before + 1);
+}
+
+fn spawn_moving_customer(app: &mut App, offset: Vec2, movement: CustomerMovement) -> Entity {
+    let pos = car(app).pos + offset;
+    app.world
+        .spawn((
+            Customer::new(pos, Merch::Banana, None, movement),
+            PartOfLevel,
+        ))
+        .id()
+}
+
+/// The walls either side of `pos`
+fn walls_beside(app: &mut App, pos: Vec2) -> (f32, f32) {
+    let mut obstacles = app.world.query::<&Obstacle>();
+    let beside: Vec<f32> = obstacles
+        .iter(&app.world)
+        .filter(|obstacle| (obstacle.pos.y - pos.y).abs() <= HEIGHT_OF_WALL / 2.)
+        .map(|obstacle| obstacle.pos.x)
+        .collect();
+    let left = beside.iter().copied().fold(f32::INFINITY, f32::min);
+    let right = beside.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    (left, right)
+}
+
+#[test]
+fn cruising_customers_follow_their_lane() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    // the first level's first right turn starts just ahead
+    let customer = spawn_moving_customer(
+        &mut app,
+        Vec2::new(-300., 7100.),
+        CustomerMovement::Cruise { speed: 20. },
+    );
+    let start = app.world.get::<Customer>(customer).unwrap().pos;
+    for _ in 0..150 {
+        app.update();
+        let pos = app.world.get::<Customer>(customer).unwrap().pos;
+        let (left, right) = walls_beside(&mut app, pos);
+        assert!(left < pos.x && pos.x < right);
+    }
+    let pos = app.world.get::<Customer>(customer).unwrap().pos;
+    assert!((pos.y - start.y - 3000.).abs() < 1e-2);
+    assert!(pos.x > start.x + 400.);
+}
+
+#[test]
+fn cruising_customers_stay_inside_a_narrowing_lane() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    // the first level's first target area is wider than the turn after it
+    let customer = spawn_moving_customer(
+        &mut app,
+        Vec2::new(500., 6700.),
+        CustomerMovement::Cruise { speed: 20. },
+    );
+    for _ in 0..60 {
+        app.update();
+        let pos = app.world.get::<Customer>(customer).unwrap().pos;
+        let (left, right) = walls_beside(&mut app, pos);
+        assert!(left < pos.x && pos.x < right);
+    }
+}
+
+#[test]
+fn cruising_customers_stop_at_the_end_of_the_track() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    let mut obstacles = app.world.query::<&Obstacle>();
+    let end = obstacles
+        .iter(&app.world)
+        .map(|obstacle| obstacle.pos.y)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let (left, right) = walls_beside(&mut app, Vec2::new(0., end));
+    let customer = app
+        .world
+        .spawn((
+            Customer::new(
+                Vec2::new((left + right) / 2., end - 100.),
+                Merch::Banana,
+                None,
+                CustomerMovement::Cruise { speed: 20. },
+            ),
+            PartOfLevel,
+        ))
+        .id();
+    for _ in 0..30 {
+        app.update();
+    }
+    let pos = app.world.get::<Customer>(customer).unwrap().pos;
+    assert!(pos.y <= end);
+    app.update();
+    assert_eq!(app.world.get::<Customer>(customer).unwrap().pos, pos);
+}
+
+#[test]
+fn crossing_customers_turn_around_at_the_end_of_their_range() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    let customer = spawn_moving_customer(
+        &mut app,
+        Vec2::new(0., 3000.),
+        CustomerMovement::Cross {
+            range: 50.,
+            speed: 10.,
+        },
+    );
+    let home = app.world.get::<Customer>(customer).unwrap().pos;
+    let mut furthest = 0.0f32;
+    let mut went_left = false;
+    for _ in 0..40 {
+        app.update();
+        let offset = app.world.get::<Customer>(customer).unwrap().pos.x - home.x;
+        furthest = furthest.max(offset.abs());
+        went_left |= offset < 0.;
+    }
+    assert!(went_left);
+    assert!(furthest <= 60.);
+}
+
+#[test]
+fn throws_have_to_lead_moving_customers() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    despawn_customers(&mut app);
+    despawn_walls(&mut app);
+    let shoot = TickInput {
+        shoot_left: true,
+        ..default()
+    };
+
+    // aimed straight at it, the banana passes behind the customer's car, which keeps to the
+    // lane even with the walls gone
+    let moving = spawn_moving_customer(
+        &mut app,
+        Vec2::new(-350., 0.),
+        CustomerMovement::Cruise { speed: 120. },
+    );
+    *app.world.resource_mut::<TickInput>() = shoot;
+    for _ in 0..20 {
+        app.update();
+    }
+    assert!(app.world.get_entity(moving).is_some());
+    assert_eq!(car(&mut app).delivered, 0);
+
+    // the same throw reaches a parked customer
+    let parked = spawn_moving_customer(&mut app, Vec2::new(-350., 0.), CustomerMovement::Parked);
+    *app.world.resource_mut::<TickInput>() = shoot;
+    for _ in 0..20 {
+        app.update();
+    }
+    assert!(app.world.get_entity(parked).is_none());
+    assert_eq!(car(&mut app).delivered, 1);
+}
+
+#[test]
+fn reaching_the_goal_with_customers_left_loses() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    spawn_goal_ahead(&mut app);
+    let end = drive_until_end(&mut app, ACCELERATE, 600);
+    assert!(matches!(
+        end,
+        AppState::EndLevel {
+            did_win: false,
+            did_finish: true,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn a_customer_leaving_as_the_car_reaches_the_goal_loses() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    despawn_customers(&mut app);
+    let pos = car(&mut app).pos;
+    app.world.spawn((
+        Customer::new(
+            pos - Vec2::new(500., 0.),
+            Merch::Banana,
+            Some(0.0),
+            CustomerMovement::Parked,
+        ),
+        PartOfLevel,
+    ));
+    // the car is already past the line, so both happen in the next tick
+    app.world.spawn((
+        Goal {
+            pos: pos - Vec2::new(0., 1.),
+            radius: 300.,
+        },
+        PartOfLevel,
+    ));
+    let end = drive_until_end(&mut app, TickInput::default(), 3);
+    assert!(matches!(
+        end,
+        AppState::EndLevel {
+            did_win: false,
+            did_finish: true,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn reaching_the_goal_after_every_delivery_wins_and_is_saved() {
+    let mut app = headless_app();
+    let runs_before = app.world.query::<&SaveData>().single(&app.world).runs.len();
+    start(&mut app, false);
+    despawn_customers(&mut app);
+    spawn_goal_ahead(&mut app);
+    let end = drive_until_end(&mut app, ACCELERATE, 600);
+    assert!(matches!(
+        end,
+        AppState::EndLevel {
+            level: 0,
+            did_win: true,
+            did_finish: true,
+            ..
+        }
+    ));
+    // the run is recorded in the update that enters the end state
+    let save = app.world.query::<&SaveData>().single(&app.world);
+    assert_eq!(save.runs.len(), runs_before + 1);
+}
+
+#[test]
+fn replaying_a_run_gives_the_same_result() {
+    let mut app = headless_app();
+    start(&mut app, true);
+    drive_until_end(&mut app, STEER_LEFT, 600);
+    app.update();
+
+    press(
+        &mut app,
+        MenuInput {
+            replay: true,
+            ..default()
+        },
+    );
+    // the replay starts by itself once the track is respawned
+    for _ in 0..10 {
+        app.update();
+        if state(&app) == AppState::Game {
+            break;
+        }
+    }
+    assert_eq!(state(&app), AppState::Game);
+    // the keyboard is ignored while a replay plays
+    drive_until_end(&mut app, TickInput::default(), 600);
+    app.update();
+    assert_eq!(
+        app.world.resource::<ReplayState>().verification,
+        Some(Verification::Verified)
+    );
+}
+
+#[test]
+fn watching_a_won_run_does_not_save_it_again() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    despawn_customers(&mut app);
+    let goal = car(&mut app).pos + Vec2::new(0., 300.);
+    app.world.spawn((
+        Goal {
+            pos: goal,
+            radius: 300.,
+        },
+        PartOfLevel,
+    ));
+    drive_until_end(&mut app, ACCELERATE, 600);
+    app.update();
+    let runs = |app: &mut App| app.world.query::<&SaveData>().single(&app.world).runs.len();
+    assert_eq!(runs(&mut app), 1);
+
+    press(
+        &mut app,
+        MenuInput {
+            replay: true,
+            ..default()
+        },
+    );
+    for _ in 0..10 {
+        app.update();
+        if state(&app) == AppState::Game {
+            break;
+        }
+    }
+    assert_eq!(state(&app), AppState::Game);
+    // the track was respawned, so set it up like the recorded run had it
+    despawn_customers(&mut app);
+    app.world.spawn((
+        Goal {
+            pos: goal,
+            radius: 300.,
+        },
+        PartOfLevel,
+    ));
+    let end = drive_until_end(&mut app, TickInput::default(), 600);
+    assert!(matches!(end, AppState::EndLevel { did_win: true, .. }));
+    app.update();
+    assert_eq!(runs(&mut app), 1);
+}
+
+#[test]
+fn watching_a_won_run_does_not_replace_the_best_or_last_replay() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    despawn_customers(&mut app);
+    let goal = car(&mut app).pos + Vec2::new(0., 300.);
+    app.world.spawn((
+        Goal {
+            pos: goal,
+            radius: 300.,
+        },
+        PartOfLevel,
+    ));
+    drive_until_end(&mut app, ACCELERATE, 600);
+    app.update();
+
+    // a slower best and another last run, which the watched run would replace if stored
+    let storage = app.world.resource::<Storage>();
+    let best_name = best_replay_name(0, false);
+    let mut slower = Replay::parse(&storage.read(&best_name).unwrap()).unwrap();
+    slower.outcome.as_mut().unwrap().ticks += 100;
+    storage.store(&best_name, &slower);
+    storage.store(LAST_REPLAY_NAME, &slower);
+    let stored = |app: &App| {
+        let storage = app.world.resource::<Storage>();
+        (
+            storage.read(&best_name).unwrap(),
+            storage.read(LAST_REPLAY_NAME).unwrap(),
+        )
+    };
+    let before = stored(&app);
+
+    press(
+        &mut app,
+        MenuInput {
+            replay: true,
+            ..default()
+        },
+    );
+    for _ in 0..10 {
+        app.update();
+        if state(&app) == AppState::Game {
+            break;
+        }
+    }
+    assert_eq!(state(&app), AppState::Game);
+    despawn_customers(&mut app);
+    app.world.spawn((
+        Goal {
+            pos: goal,
+            radius: 300.,
+        },
+        PartOfLevel,
+    ));
+    let end = drive_until_end(&mut app, TickInput::default(), 600);
+    assert!(matches!(end, AppState::EndLevel { did_win: true, .. }));
+    app.update();
+    assert_eq!(stored(&app), before);
+}
+
+#[test]
+fn winning_a_level_unlocks_the_next_one() {
+    let mut app = headless_app();
+    let unlocked = |app: &mut App| {
+        let save = app.world.query::<&SaveData>().single(&app.world);
+        save.is_unlocked(1)
+    };
+    assert!(!unlocked(&mut app));
+    start(&mut app, false);
+    win(&mut app);
+    assert!(unlocked(&mut app));
+}
+
+#[test]
+fn next_level_goes_on_to_the_following_level() {
+    let mut app = headless_app();
+    start(&mut app, false);
+    win(&mut app);
+    press(
+        &mut app,
+        MenuInput {
+            next_level: true,
+            ..default()
+        },
+    );
+    app.update();
+    assert_eq!(state(&app), AppState::StartLevel(1));
+    assert_eq!(app.world.resource::<CurrentLevel>().index, 1);
+}
+
+#[test]
+fn locked_levels_cannot_be_picked() {
+    let mut app = headless_app();
+    press(
+        &mut app,
+        MenuInput {
+            select_right: true,
+            ..default()
+        },
+    );
+    app.update();
+    assert_eq!(state(&app), AppState::StartLevel(0));
+    assert_eq!(app.world.resource::<CurrentLevel>().index, 0);
+}
+
+#[test]
+fn the_last_level_does_not_go_past_the_end() {
+    let mut app = headless_app();
+    let last = CAMPAIGN.len() - 1;
+    // unlock every level by winning them in turn
+    for level in 0..last {
+        start(&mut app, false);
+        win(&mut app);
+        press(
+            &mut app,
+            MenuInput {
+                next_level: true,
+                ..default()
+            },
+        );
+        wait_for_level(&mut app);
+        assert_eq!(state(&app), AppState::StartLevel(level + 1));
+    }
+    press(
+        &mut app,
+        MenuInput {
+            select_right: true,
+            ..default()
+        },
+    );
+    app.update();
+    assert_eq!(app.world.resource::<CurrentLevel>().index, last);
+
+    start(&mut app, false);
+    win(&mut app);
+    let end = state(&app);
+    press(
+        &mut app,
+        MenuInput {
+            next_level: true,
+            ..default()
+        },
+    );
+    app.update();
+    assert_eq!(state(&app), end);
+    assert_eq!(app.world.resource::<CurrentLevel>().index, last);
+}
+
+#[test]
+fn the_car_drives_the_same_at_another_tick_rate() {
+    // one second of driving, every update is a 60th of a second
+    let drive_one_second = |mut app: App| {
+        start(&mut app, false);
+        for _ in 0..60 {
+            *app.world.resource_mut::<TickInput>() = ACCELERATE;
+            app.update();
+        }
+        let ticks = car(&mut app).ticks_elapsed;
+        let tick_rate = app
+            .world
+            .resource::<ReplayState>()
+            .recording
+            .as_ref()
+            .unwrap()
+            .tick_rate;
+        (car(&mut app).pos.y, ticks, tick_rate)
+    };
+    let (reference, reference_ticks, _) = drive_one_second(headless_app());
+    let (y, ticks, tick_rate) = drive_one_second(app_with(
+        SimulationPlugins
+            .build()
+            .set(CorePlugin { tick_rate: 30.0 }),
+    ));
+    assert_eq!(tick_rate, 30.0);
+    assert!(ticks < reference_ticks);
+    assert!(
+        (y - reference).abs() < reference * 0.1,
+        "{y} vs {reference}"
+    );
+}
+
+/// Makes `CurrentLevel` level `index` of the registry, to be spawned again
+fn switch_level(app: &mut App, index: usize) {
+    let mut current_level = app.world.resource_mut::<CurrentLevel>();
+    current_level.index = index;
+    current_level.spawned = false;
+}
+
+#[test]
+fn a_level_that_fails_to_load_says_why() {
+    let mut app = headless_app();
+    let missing: Handle<Level> = app
+        .world
+        .resource::<AssetServer>()
+        .load("levels/missing.level.ron");
+    let mut registry = app.world.resource_mut::<LevelRegistry>();
+    registry.levels.push(missing);
+    let index = registry.levels.len() - 1;
+    switch_level(&mut app, index);
+    for _ in 0..1000 {
+        app.update();
+        if app.world.resource::<CurrentLevel>().error.is_some() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let current_level = app.world.resource::<CurrentLevel>();
+    let error = current_level
+        .error
+        .as_ref()
+        .expect("the level never failed");
+    assert!(error.contains("missing.level.ron"), "{error}");
+    assert!(!current_level.spawned);
+}
+
+#[test]
+fn a_level_that_does_not_exist_is_an_error_instead_of_a_panic() {
+    let mut app = headless_app();
+    switch_level(&mut app, CAMPAIGN.len());
+    app.update();
+    let current_level = app.world.resource::<CurrentLevel>();
+    assert!(current_level.error.is_some());
+    assert!(!current_level.spawned);
+}